@@ -0,0 +1,162 @@
+//! Holds information about block IDs, and the fallbacks for blocks that not every client supports.
+#![allow(clippy::cast_possible_truncation)]
+
+use std::collections::BTreeMap;
+use mint::Vector3;
+use crate::{extensions::{Extension, SupportedExtensions}, packets::Outgoing};
+
+/// The highest block ID that every client supports.
+pub const MAX_VANILLA_BLOCK: u8 = 49;
+/// The highest block ID added by the `CustomBlocks` extension.
+pub const MAX_CUSTOM_BLOCK: u8 = 65;
+/// The highest `CustomBlocks` support level the server supports.
+pub const CUSTOM_BLOCK_SUPPORT_LEVEL: u8 = 1;
+
+/// Blocks that only ranks of at least [`crate::ranks::OPERATOR_LEVEL`] can place or delete by default.
+/// These are bedrock, water, and lava.
+pub const OPERATOR_BLOCKS: [u8; 5] = [7, 8, 9, 10, 11];
+
+/// Vanilla fallbacks for the blocks added by `CustomBlocks`, starting at [`MAX_VANILLA_BLOCK`] + 1.
+static CUSTOM_BLOCK_FALLBACKS: [u8; (MAX_CUSTOM_BLOCK - MAX_VANILLA_BLOCK) as usize] = [
+    44, // Cobblestone slab -> Slab
+    39, // Rope -> Brown mushroom
+    12, // Sandstone -> Sand
+    0,  // Snow -> Air
+    10, // Fire -> Lava
+    33, // Light pink wool -> Pink wool
+    25, // Forest green wool -> Green wool
+    3,  // Brown wool -> Dirt
+    29, // Deep blue wool -> Blue wool
+    28, // Turquoise wool -> Cyan wool
+    20, // Ice -> Glass
+    42, // Ceramic tile -> Iron block
+    49, // Magma -> Obsidian
+    36, // Pillar -> White wool
+    5,  // Crate -> Planks
+    1,  // Stone brick -> Stone
+];
+
+/// Gets the block that a client with the given `CustomBlocks` support level should see in place of a block.
+#[must_use]
+pub fn fallback(id: u8, support_level: u8) -> u8 {
+    if support_level >= CUSTOM_BLOCK_SUPPORT_LEVEL || !(MAX_VANILLA_BLOCK + 1..=MAX_CUSTOM_BLOCK).contains(&id) {
+        id
+    } else {
+        CUSTOM_BLOCK_FALLBACKS[(id - MAX_VANILLA_BLOCK - 1) as usize]
+    }
+}
+
+/// A custom block definition, sent to clients that support `BlockDefinitions`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockDefinition {
+    /// The block's name.
+    pub name: String,
+    /// How players collide with the block.
+    /// 0 is walk-through, 1 is swim-through, and 2 is solid.
+    pub solidity: u8,
+    /// How fast players move on the block. 128 is normal speed.
+    pub speed: u8,
+    /// The block's texture IDs, in the order of top, left, right, front, back, and bottom.
+    pub textures: [u8; 6],
+    /// Whether the block lets light through.
+    pub transmits_light: bool,
+    /// The sound played when walking on the block.
+    pub walk_sound: u8,
+    /// Whether the block is always fully lit.
+    pub full_bright: bool,
+    /// Whether the block is drawn as a sprite, like saplings and flowers.
+    pub sprite: bool,
+    /// The minimum corner of the block's bounding box, from 0 to 16.
+    pub min: Vector3<u8>,
+    /// The maximum corner of the block's bounding box, from 0 to 16.
+    pub max: Vector3<u8>,
+    /// How the block is drawn. 0 is opaque, 1 is transparent, 2 is transparent with no culling,
+    /// 3 is translucent, and 4 is gas.
+    pub draw: u8,
+    /// The density of the fog when inside the block. 0 disables the fog.
+    pub fog_density: u8,
+    /// The color of the fog when inside the block.
+    pub fog_color: [u8; 3],
+    /// The block shown to clients that don't support `BlockDefinitions`.
+    pub fallback: u8,
+}
+
+impl BlockDefinition {
+    /// Creates a new solid, full-sized block definition using the same texture on every side.
+    #[must_use]
+    pub fn new(name: String, texture: u8, fallback: u8) -> Self {
+        Self {
+            name,
+            solidity: 2,
+            speed: 128,
+            textures: [texture; 6],
+            transmits_light: false,
+            walk_sound: 1,
+            full_bright: false,
+            sprite: false,
+            min: Vector3 { x: 0, y: 0, z: 0 },
+            max: Vector3 { x: 16, y: 16, z: 16 },
+            draw: 0,
+            fog_density: 0,
+            fog_color: [0; 3],
+            fallback,
+        }
+    }
+
+    /// Gets the packet that defines this block for a client with the given extensions,
+    /// or `None` if the client doesn't support block definitions.
+    #[must_use]
+    pub fn packet(&self, id: u8, extensions: SupportedExtensions) -> Option<Outgoing> {
+        // Sprites can't be defined with DefineBlockExt
+        if extensions.contains(Extension::BlockDefinitionsExt) && !self.sprite {
+            Some(Outgoing::DefineBlockExt { id, definition: self.clone() })
+        } else if extensions.contains(Extension::BlockDefinitions) {
+            Some(Outgoing::DefineBlock { id, definition: self.clone() })
+        } else {
+            None
+        }
+    }
+}
+
+/// Removes air and repeated blocks from a palette, keeping the first of each,
+/// so that it can hold at most one entry for each of the 255 orderable blocks.
+pub fn clean_palette(palette: &mut Vec<u8>) {
+    let mut seen = [false; 256];
+    palette.retain(|&id| id != 0 && !std::mem::replace(&mut seen[usize::from(id)], true));
+}
+
+/// Gets the packets that order a client's inventory to match a palette, for clients that support `InventoryOrder`.
+///
+/// Blocks in the palette are shown in the order they're listed, and every other block is hidden.
+/// If the palette is empty, then every block is put back in order of its ID.
+#[must_use]
+pub fn inventory_order(palette: &[u8]) -> Vec<Outgoing> {
+    (1..=u8::MAX)
+        .map(|block| {
+            let order = if palette.is_empty() {
+                block
+            } else {
+                palette.iter()
+                    .position(|&id| id == block)
+                    .and_then(|index| u8::try_from(index + 1).ok())
+                    .unwrap_or(0)
+            };
+            Outgoing::SetInventoryOrder { block, order }
+        })
+        .collect()
+}
+
+/// Creates a lookup table of what every block should be replaced with for a client with the given `CustomBlocks` support level.
+///
+/// If the client doesn't support `BlockDefinitions`, then `definitions` should hold the world's block definitions,
+/// which will be replaced with their fallbacks.
+#[must_use]
+pub fn fallback_table(support_level: u8, definitions: Option<&BTreeMap<u8, BlockDefinition>>) -> [u8; 256] {
+    std::array::from_fn(|id| {
+        let id = id as u8;
+        let id = definitions
+            .and_then(|definitions| definitions.get(&id))
+            .map_or(id, |definition| definition.fallback);
+        fallback(id, support_level)
+    })
+}
@@ -0,0 +1,1926 @@
+//! Holds the commands that players and the console can run, and the parsing of their arguments.
+//!
+//! Every command is registered in [`COMMANDS`], which `/help` is generated from.
+//! Adding a command only needs an entry there and an async handler taking a [`Context`].
+
+use std::{str::SplitAsciiWhitespace, sync::Arc, time::Duration};
+use codepage_437::{CP437_WINGDINGS, ToCp437, Cp437Error};
+use futures::future::BoxFuture;
+use itertools::Itertools;
+use tokio::time;
+use uuid::Uuid;
+use crate::{
+    blocks::{self, BlockDefinition},
+    extensions::Extension,
+    packets::{Location, Vector3, x32},
+    player::{Model, WeakPlayer, PLAYER_SELECTION},
+    ranks::{self, Rank},
+    server::RunningServer,
+    world::{
+        BlockRejection, Environment, HackPolicy, HotKey, LevelData, RankRequirement, Selection, World, WorldData,
+        WorldPermissions, DEFAULT_REACH,
+    },
+};
+
+/// The result of running a command. `Ok(true)` means that the sender should stop handling packets.
+pub type CommandResult = Result<bool, String>;
+
+/// Runs a command with its parsed arguments.
+type Handler = fn(Context) -> BoxFuture<'static, CommandResult>;
+
+/// Wraps an async handler function into a [`Handler`].
+macro_rules! handler {
+    ($handler: ident) => {
+        |context| Box::pin($handler(context))
+    };
+}
+
+/// The kind of value that a command argument takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentKind {
+    /// The name of a player who's online.
+    Player,
+    /// The name of any player, whether they're online or not.
+    Username,
+    /// The name of a loaded world.
+    /// If this is the last argument, then it takes the rest of the line, since world names can contain spaces.
+    World,
+    /// A whole number.
+    Integer,
+    /// A decimal number.
+    Decimal,
+    /// A block ID.
+    Block,
+    /// Three whole numbers, for the X, Y, and Z coordinates of a position.
+    Coordinates,
+    /// A single word.
+    Word,
+    /// One of a fixed set of words.
+    Choice(&'static [&'static str]),
+    /// The rest of the line.
+    Rest,
+}
+
+/// A single argument that a command takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argument {
+    /// The name of the argument, shown in `/help` and used to get its value.
+    pub name: &'static str,
+    /// The kind of value the argument takes.
+    pub kind: ArgumentKind,
+    /// Whether the argument can be left out. Optional arguments must come after every required argument.
+    pub optional: bool,
+}
+
+impl Argument {
+    /// Creates an argument that must be given.
+    #[must_use]
+    pub const fn required(name: &'static str, kind: ArgumentKind) -> Self {
+        Self { name, kind, optional: false }
+    }
+
+    /// Creates an argument that can be left out.
+    #[must_use]
+    pub const fn optional(name: &'static str, kind: ArgumentKind) -> Self {
+        Self { name, kind, optional: true }
+    }
+
+    /// Formats the argument for a command's usage, like `<name>` or `[a|b]`.
+    /// Coordinates are shown as their three components, like `[<start x> <start y> <start z>]`.
+    fn usage(&self) -> String {
+        let inner = match self.kind {
+            ArgumentKind::Choice(choices) => choices.join("|"),
+            ArgumentKind::Coordinates => {
+                let components = ["x", "y", "z"].map(|axis| format!("<{} {axis}>", self.name)).join(" ");
+                return if self.optional { format!("[{components}]") } else { components }
+            }
+            ArgumentKind::Rest => format!("{}...", self.name),
+            _ => self.name.to_string(),
+        };
+        if self.optional { format!("[{inner}]") } else { format!("<{inner}>") }
+    }
+}
+
+/// A command that players and the console can run.
+#[derive(Debug, Clone, Copy)]
+pub struct Command {
+    /// The name that the command is run with.
+    pub name: &'static str,
+    /// Other names that the command can be run with.
+    pub aliases: &'static [&'static str],
+    /// The arguments that the command's handler takes.
+    pub arguments: &'static [Argument],
+    /// The permission needed to run the command, or `None` if everyone can run it.
+    pub permission: Option<&'static str>,
+    /// A short description of the command, shown in `/help`.
+    pub description: &'static str,
+    /// The handler that runs the command, or `None` if it can only be run through a subcommand.
+    pub handler: Option<Handler>,
+    /// The subcommands of the command, which are picked by the first argument.
+    pub subcommands: &'static [Command],
+}
+
+impl Command {
+    /// Creates a command with no arguments, aliases, or subcommands, that everyone can run.
+    #[must_use]
+    pub const fn new(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            arguments: &[],
+            permission: None,
+            description,
+            handler: None,
+            subcommands: &[],
+        }
+    }
+
+    /// Sets the other names that the command can be run with.
+    #[must_use]
+    pub const fn aliases(self, aliases: &'static [&'static str]) -> Self {
+        Self { aliases, ..self }
+    }
+
+    /// Sets the arguments that the command's handler takes.
+    #[must_use]
+    pub const fn arguments(self, arguments: &'static [Argument]) -> Self {
+        Self { arguments, ..self }
+    }
+
+    /// Sets the permission needed to run the command.
+    #[must_use]
+    pub const fn permission(self, permission: &'static str) -> Self {
+        Self { permission: Some(permission), ..self }
+    }
+
+    /// Sets the handler that runs the command.
+    #[must_use]
+    pub const fn run(self, handler: Handler) -> Self {
+        Self { handler: Some(handler), ..self }
+    }
+
+    /// Sets the subcommands of the command.
+    #[must_use]
+    pub const fn subcommands(self, subcommands: &'static [Command]) -> Self {
+        Self { subcommands, ..self }
+    }
+
+    /// Checks if the command can be run with a name.
+    fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+
+    /// Checks if a player with a rank can run the command.
+    fn allows(&self, rank: &Rank) -> bool {
+        self.permission.is_none_or(|permission| rank.has(permission))
+    }
+
+    /// Formats how the command is run, like `/world create <length> [seed]`.
+    fn usage(&self, path: &str) -> String {
+        let mut usage = format!("/{path}");
+        for argument in self.arguments {
+            usage.push(' ');
+            usage.push_str(&argument.usage());
+        }
+        usage
+    }
+}
+
+/// A parsed argument value.
+#[derive(Debug, Clone)]
+enum Value {
+    /// An online player, with the name they were given by.
+    Player(String, WeakPlayer),
+    /// A word, a username, or the rest of the line.
+    Text(String),
+    /// A loaded world.
+    World(World),
+    /// A whole number.
+    Integer(i64),
+    /// A decimal number.
+    Decimal(f32),
+    /// A block ID.
+    Block(u8),
+    /// A position.
+    Coordinates(Vector3<i32>),
+}
+
+/// The parsed arguments of a command, looked up by name.
+#[derive(Debug, Clone, Default)]
+pub struct Arguments {
+    values: Vec<(&'static str, Value)>,
+}
+
+impl Arguments {
+    /// Gets the value of an argument.
+    fn get(&self, name: &str) -> Result<&Value, String> {
+        self.values.iter()
+            .find(|(argument, _)| *argument == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("No {name} specified"))
+    }
+
+    /// Gets the name of an online player and the player.
+    ///
+    /// # Errors
+    /// Errors if the argument wasn't given, or isn't a [`ArgumentKind::Player`].
+    pub fn player(&self, name: &str) -> Result<(&str, &WeakPlayer), String> {
+        match self.get(name)? {
+            Value::Player(username, player) => Ok((username, player)),
+            _ => Err(format!("{name} isn't a player")),
+        }
+    }
+
+    /// Gets a word, a username, or the rest of the line.
+    ///
+    /// # Errors
+    /// Errors if the argument wasn't given, or isn't text.
+    pub fn text(&self, name: &str) -> Result<&str, String> {
+        match self.get(name)? {
+            Value::Text(text) => Ok(text),
+            _ => Err(format!("{name} isn't text")),
+        }
+    }
+
+    /// Gets a loaded world.
+    ///
+    /// # Errors
+    /// Errors if the argument wasn't given, or isn't a [`ArgumentKind::World`].
+    pub fn world(&self, name: &str) -> Result<&World, String> {
+        match self.get(name)? {
+            Value::World(world) => Ok(world),
+            _ => Err(format!("{name} isn't a world")),
+        }
+    }
+
+    /// Gets a whole number.
+    ///
+    /// # Errors
+    /// Errors if the argument wasn't given, or isn't a [`ArgumentKind::Integer`].
+    pub fn integer(&self, name: &str) -> Result<i64, String> {
+        match self.get(name)? {
+            Value::Integer(integer) => Ok(*integer),
+            _ => Err(format!("{name} isn't a whole number")),
+        }
+    }
+
+    /// Gets a decimal number.
+    ///
+    /// # Errors
+    /// Errors if the argument wasn't given, or isn't a [`ArgumentKind::Decimal`].
+    pub fn decimal(&self, name: &str) -> Result<f32, String> {
+        match self.get(name)? {
+            Value::Decimal(decimal) => Ok(*decimal),
+            _ => Err(format!("{name} isn't a number")),
+        }
+    }
+
+    /// Gets a block ID.
+    ///
+    /// # Errors
+    /// Errors if the argument wasn't given, or isn't a [`ArgumentKind::Block`].
+    pub fn block(&self, name: &str) -> Result<u8, String> {
+        match self.get(name)? {
+            Value::Block(block) => Ok(*block),
+            _ => Err(format!("{name} isn't a block")),
+        }
+    }
+
+    /// Gets a position.
+    ///
+    /// # Errors
+    /// Errors if the argument wasn't given, or isn't a [`ArgumentKind::Coordinates`].
+    pub fn coordinates(&self, name: &str) -> Result<Vector3<i32>, String> {
+        match self.get(name)? {
+            Value::Coordinates(coordinates) => Ok(*coordinates),
+            _ => Err(format!("{name} isn't a position")),
+        }
+    }
+}
+
+/// Who ran a command.
+#[derive(Debug, Clone)]
+pub enum CommandSender {
+    /// A player in the game.
+    Player(WeakPlayer),
+    /// The server's console, which has every permission.
+    Console,
+}
+
+/// Everything a command's handler has access to.
+#[derive(Debug, Clone)]
+pub struct Context {
+    /// Who ran the command.
+    pub sender: CommandSender,
+    /// The server the command was run in.
+    pub server: RunningServer,
+    /// The rank of whoever ran the command.
+    pub rank: Rank,
+    /// The parsed arguments of the command.
+    pub args: Arguments,
+}
+
+impl Context {
+    /// Sends a message to whoever ran the command. Messages to the console are logged without color codes.
+    async fn reply(&self, message: impl Into<String>) {
+        match &self.sender {
+            CommandSender::Player(player) => player.send_message(message).await,
+            CommandSender::Console => info!("{}", strip_colors(&message.into())),
+        }
+    }
+
+    /// Gets the player who ran the command.
+    ///
+    /// # Errors
+    /// Errors if the command was run from the console.
+    fn player(&self) -> Result<&WeakPlayer, String> {
+        match &self.sender {
+            CommandSender::Player(player) => Ok(player),
+            CommandSender::Console => Err("This command can only be used by players".into()),
+        }
+    }
+
+    /// Gets the username of whoever ran the command.
+    fn username(&self) -> String {
+        match &self.sender {
+            CommandSender::Player(player) => player.username.upgrade().and_then(|name| name.get().cloned()).unwrap_or_default(),
+            CommandSender::Console => "Console".into(),
+        }
+    }
+
+    /// Gets the world that the player who ran the command is in.
+    fn world(&self) -> Result<World, String> {
+        self.player()?.world.upgrade()
+            .map(|world| world.lock().clone())
+            .ok_or("You aren't in a world".into())
+    }
+
+    /// Gets the player given by an optional argument, or the player who ran the command if it wasn't given.
+    fn player_or_self(&self, name: &str) -> Result<(String, WeakPlayer), String> {
+        match self.args.player(name) {
+            Ok((username, player)) => Ok((username.to_string(), player.clone())),
+            Err(_) => Ok((self.username(), self.player()?.clone())),
+        }
+    }
+}
+
+/// The most blocks that worlds made with `/world create` can have.
+const MAX_WORLD_VOLUME: u64 = 512 * 512 * 256;
+
+/// The most blocks that `/fill` can change at once.
+const MAX_FILL_VOLUME: usize = 128 * 128 * 128;
+
+/// Every command that can be run, in the order they're listed in `/help`.
+pub static COMMANDS: &[Command] = &[
+    Command::new("world", "Manages worlds").subcommands(&[
+        Command::new("join", "Joins a world")
+            .aliases(&["goto"])
+            .arguments(&[Argument::required("world", ArgumentKind::World)])
+            .run(handler!(world_join)),
+        Command::new("list", "Lists the loaded worlds")
+            .run(handler!(world_list)),
+        Command::new("hacks", "Shows the hacks allowed in the world")
+            .run(handler!(world_hacks))
+            .subcommands(&[
+                Command::new("set", "Changes a hack allowed in the world")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("hack", ArgumentKind::Word), Argument::required("value", ArgumentKind::Word)])
+                    .run(handler!(world_hacks_set)),
+            ]),
+        Command::new("reach", "Shows how far players can reach")
+            .run(handler!(world_reach))
+            .subcommands(&[
+                Command::new("set", "Changes how far players can reach, or resets it")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("blocks", ArgumentKind::Word)])
+                    .run(handler!(world_reach_set)),
+            ]),
+        Command::new("hotkey", "Lists the world's hotkeys")
+            .run(handler!(world_hotkey))
+            .subcommands(&[
+                Command::new("bind", "Binds a key to a chat message or command")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("key", ArgumentKind::Word), Argument::required("action", ArgumentKind::Rest)])
+                    .run(handler!(world_hotkey_bind)),
+                Command::new("unbind", "Unbinds a key")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("key", ArgumentKind::Word)])
+                    .run(handler!(world_hotkey_unbind)),
+            ]),
+        Command::new("palette", "Shows the world's inventory order")
+            .run(handler!(world_palette))
+            .subcommands(&[
+                Command::new("set", "Replaces the world's inventory order")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("blocks", ArgumentKind::Rest)])
+                    .run(handler!(world_palette_set)),
+                Command::new("add", "Adds blocks to the end of the world's inventory order")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("blocks", ArgumentKind::Rest)])
+                    .run(handler!(world_palette_add)),
+                Command::new("remove", "Removes blocks from the world's inventory order")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("blocks", ArgumentKind::Rest)])
+                    .run(handler!(world_palette_remove)),
+                Command::new("reset", "Goes back to the default inventory")
+                    .permission(ranks::WORLD_EDIT)
+                    .run(handler!(world_palette_reset)),
+            ]),
+        Command::new("access", "Shows who can place and delete blocks")
+            .run(handler!(world_access))
+            .subcommands(&[
+                Command::new("set", "Changes the lowest rank that can place and delete blocks")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("rank", ArgumentKind::Word), Argument::required("blocks", ArgumentKind::Rest)])
+                    .run(handler!(world_access_set)),
+                Command::new("reset", "Goes back to the default access for blocks")
+                    .permission(ranks::WORLD_EDIT)
+                    .arguments(&[Argument::required("blocks", ArgumentKind::Rest)])
+                    .run(handler!(world_access_reset)),
+            ]),
+        Command::new("perms", "Shows or changes the world's owner, builders, and ranks")
+            .arguments(&[
+                Argument::optional("property", ArgumentKind::Choice(&["owner", "builders", "visit", "build"])),
+                Argument::optional("value", ArgumentKind::Rest),
+            ])
+            .run(handler!(world_perms)),
+        Command::new("spawnpoint", "Sets the world's spawn point to your location")
+            .permission(ranks::WORLD_EDIT)
+            .run(handler!(world_spawnpoint)),
+        Command::new("env", "Changes the world's environment")
+            .permission(ranks::WORLD_EDIT)
+            .arguments(&[Argument::required("property", ArgumentKind::Word), Argument::required("value", ArgumentKind::Rest)])
+            .run(handler!(world_env)),
+        Command::new("create", "Generates a new world")
+            .permission(ranks::WORLD_MANAGE)
+            .arguments(&[
+                Argument::required("length", ArgumentKind::Integer),
+                Argument::required("width", ArgumentKind::Integer),
+                Argument::required("height", ArgumentKind::Integer),
+                Argument::required("generator", ArgumentKind::Word),
+                Argument::optional("seed", ArgumentKind::Word),
+            ])
+            .run(handler!(world_create)),
+        Command::new("rename", "Renames the world you're in")
+            .permission(ranks::WORLD_MANAGE)
+            .arguments(&[Argument::required("name", ArgumentKind::Rest)])
+            .run(handler!(world_rename)),
+        Command::new("save", "Saves the world you're in")
+            .permission(ranks::WORLD_MANAGE)
+            .run(handler!(world_save)),
+        Command::new("generators", "Lists the world generators")
+            .permission(ranks::WORLD_MANAGE)
+            .run(handler!(world_generators)),
+    ]),
+    Command::new("block", "Manages custom blocks").subcommands(&[
+        Command::new("list", "Lists the world's custom blocks")
+            .run(handler!(block_list)),
+        Command::new("define", "Defines a custom block")
+            .permission(ranks::BLOCK_DEFINE)
+            .arguments(&[
+                Argument::required("id", ArgumentKind::Block),
+                Argument::required("fallback", ArgumentKind::Block),
+                Argument::required("name", ArgumentKind::Rest),
+            ])
+            .run(handler!(block_define)),
+        Command::new("edit", "Changes a property of a custom block")
+            .permission(ranks::BLOCK_DEFINE)
+            .arguments(&[
+                Argument::required("id", ArgumentKind::Block),
+                Argument::required("property", ArgumentKind::Word),
+                Argument::required("value", ArgumentKind::Rest),
+            ])
+            .run(handler!(block_edit)),
+        Command::new("remove", "Removes a custom block")
+            .permission(ranks::BLOCK_DEFINE)
+            .arguments(&[Argument::required("id", ArgumentKind::Block)])
+            .run(handler!(block_remove)),
+    ]),
+    Command::new("hold", "Shows or changes the block you're holding")
+        .arguments(&[Argument::optional("block", ArgumentKind::Block), Argument::optional("slot", ArgumentKind::Integer)])
+        .run(handler!(hold)),
+    Command::new("select", "Shows or changes your selection")
+        .arguments(&[
+            Argument::optional("start", ArgumentKind::Coordinates),
+            Argument::optional("end", ArgumentKind::Coordinates),
+        ])
+        .run(handler!(select))
+        .subcommands(&[
+            Command::new("pos1", "Moves the first corner of your selection to you")
+                .run(handler!(select_pos1)),
+            Command::new("pos2", "Moves the second corner of your selection to you")
+                .run(handler!(select_pos2)),
+            Command::new("clear", "Clears your selection")
+                .run(handler!(select_clear)),
+        ]),
+    Command::new("w", "Sends a private message")
+        .aliases(&["msg", "tell"])
+        .arguments(&[Argument::required("player", ArgumentKind::Player), Argument::required("message", ArgumentKind::Rest)])
+        .run(handler!(whisper)),
+    Command::new("locate", "Shows which world a player is in")
+        .arguments(&[Argument::optional("player", ArgumentKind::Player)])
+        .run(handler!(locate)),
+    Command::new("ping", "Shows a player's ping")
+        .arguments(&[Argument::optional("player", ArgumentKind::Player)])
+        .run(handler!(ping)),
+    Command::new("extensions", "Shows the extensions a player's client supports")
+        .arguments(&[Argument::optional("player", ArgumentKind::Player)])
+        .run(handler!(extensions)),
+    Command::new("players", "Lists the online players")
+        .aliases(&["who"])
+        .run(handler!(players)),
+    Command::new("rank", "Shows a player's rank")
+        .arguments(&[Argument::optional("player", ArgumentKind::Username)])
+        .run(handler!(rank))
+        .subcommands(&[
+            Command::new("set", "Changes a player's rank")
+                .permission(ranks::SET_RANK)
+                .arguments(&[Argument::required("player", ArgumentKind::Username), Argument::required("rank", ArgumentKind::Word)])
+                .run(handler!(rank_set)),
+        ]),
+    Command::new("ranks", "Lists the ranks")
+        .run(handler!(list_ranks)),
+    Command::new("help", "Lists commands, or shows how to use one")
+        .arguments(&[Argument::optional("command", ArgumentKind::Rest)])
+        .run(handler!(help)),
+    Command::new("op", "Gives a player the highest rank")
+        .permission(ranks::SET_RANK)
+        .arguments(&[Argument::required("player", ArgumentKind::Username)])
+        .run(handler!(op)),
+    Command::new("deop", "Gives a player the lowest rank")
+        .permission(ranks::SET_RANK)
+        .arguments(&[Argument::required("player", ArgumentKind::Username)])
+        .run(handler!(deop)),
+    Command::new("model", "Changes a player's model")
+        .permission(ranks::PLAYER_MODEL)
+        .arguments(&[Argument::required("player", ArgumentKind::Player), Argument::required("model", ArgumentKind::Word)])
+        .run(handler!(model)),
+    Command::new("scale", "Changes the size of a player's model")
+        .permission(ranks::PLAYER_MODEL)
+        .arguments(&[Argument::required("player", ArgumentKind::Player), Argument::required("scale", ArgumentKind::Decimal)])
+        .run(handler!(scale)),
+    Command::new("launch", "Changes a player's velocity")
+        .permission(ranks::PLAYER_LAUNCH)
+        .arguments(&[
+            Argument::required("player", ArgumentKind::Player),
+            Argument::required("x", ArgumentKind::Decimal),
+            Argument::required("y", ArgumentKind::Decimal),
+            Argument::required("z", ArgumentKind::Decimal),
+            Argument::optional("mode", ArgumentKind::Choice(&["add", "set"])),
+        ])
+        .run(handler!(launch)),
+    Command::new("fill", "Fills your selection with a block")
+        .permission(ranks::FILL)
+        .arguments(&[Argument::required("block", ArgumentKind::Block)])
+        .run(handler!(fill)),
+    Command::new("kick", "Disconnects a player")
+        .permission(ranks::KICK)
+        .arguments(&[Argument::required("player", ArgumentKind::Player), Argument::optional("reason", ArgumentKind::Rest)])
+        .run(handler!(kick)),
+    Command::new("ban", "Bans a player")
+        .permission(ranks::BAN)
+        .arguments(&[Argument::required("player", ArgumentKind::Username), Argument::optional("reason", ArgumentKind::Rest)])
+        .run(handler!(ban)),
+    Command::new("unban", "Unbans a player")
+        .permission(ranks::BAN)
+        .arguments(&[Argument::required("player", ArgumentKind::Username)])
+        .run(handler!(unban)),
+    Command::new("announce", "Shows a message in the middle of everyone's screen")
+        .permission(ranks::ANNOUNCE)
+        .arguments(&[Argument::required("message", ArgumentKind::Rest)])
+        .run(handler!(announce)),
+    Command::new("stop", "Stops the server")
+        .permission(ranks::STOP)
+        .run(handler!(stop)),
+];
+
+/// Finds a command by the words it's run with, like `world create`, along with its full name.
+fn find_command<'a>(words: impl IntoIterator<Item = &'a str>) -> Option<(&'static Command, String)> {
+    let mut words = words.into_iter();
+    let first = words.next()?.trim_start_matches('/');
+    let mut command = COMMANDS.iter().find(|command| command.is_named(first))?;
+    let mut path = command.name.to_string();
+    for word in words {
+        command = command.subcommands.iter().find(|subcommand| subcommand.is_named(word))?;
+        path = format!("{path} {}", command.name);
+    }
+    Some((command, path))
+}
+
+/// Runs a command, without the leading slash.
+///
+/// # Errors
+/// Errors with a message for the sender if the command couldn't be run, or failed.
+pub async fn execute(sender: CommandSender, server: &RunningServer, line: &str) -> CommandResult {
+    let rank = match &sender {
+        CommandSender::Player(player) => {
+            let username_arc = player.username.upgrade()
+                .ok_or("Username dropped".to_string())?;
+            let Some(username) = username_arc.get()
+                else { return Err("Username not initialized".to_string()) };
+            server.config.lock().rank_of(username).clone()
+        }
+        CommandSender::Console => ranks::console(),
+    };
+
+    let mut words = line.split_ascii_whitespace();
+    let name = words.next().unwrap_or("");
+    let Some(mut command) = COMMANDS.iter().find(|command| command.is_named(name)) else {
+        return Err(format!("Invalid command {name}. See /help"))
+    };
+    let mut path = command.name.to_string();
+    loop {
+        if !command.allows(&rank) {
+            return Err(format!("You don't have permission to use /{path}"))
+        }
+        let mut peek = words.clone();
+        let subcommand = peek.next()
+            .and_then(|word| command.subcommands.iter().find(|subcommand| subcommand.is_named(word)));
+        match (subcommand, command.handler) {
+            (Some(subcommand), _) => {
+                words = peek;
+                command = subcommand;
+                path = format!("{path} {}", command.name);
+            }
+            (None, Some(handler)) => {
+                let args = parse_arguments(command.arguments, words, server, &path).await?;
+                let context = Context { sender, server: server.clone(), rank, args };
+                return handler(context).await;
+            }
+            (None, None) => return Err(match words.next() {
+                Some(word) => format!("Invalid subcommand \"{word}\". See /help {path}"),
+                None => format!("No subcommand. See /help {path}"),
+            }),
+        }
+    }
+}
+
+/// Parses the arguments of a command. `path` is the full name of the command, used in errors.
+async fn parse_arguments(
+    arguments: &[Argument],
+    mut words: SplitAsciiWhitespace<'_>,
+    server: &RunningServer,
+    path: &str,
+) -> Result<Arguments, String> {
+    let mut values = Vec::with_capacity(arguments.len());
+    for (index, argument) in arguments.iter().enumerate() {
+        let last = index + 1 == arguments.len();
+        let text = match argument.kind {
+            ArgumentKind::Rest => rest(&mut words),
+            ArgumentKind::World if last => rest(&mut words),
+            _ => words.next(),
+        };
+        let Some(text) = text.map(str::trim_end) else {
+            if argument.optional {
+                break;
+            }
+            return Err(format!("No {} specified. See /help {path}", argument.name))
+        };
+        let name = argument.name;
+        let value = match argument.kind {
+            ArgumentKind::Player => {
+                let Some(player) = server.connected_players.lock().await.get(text).cloned() else {
+                    return Err(format!("User {text} is not online"))
+                };
+                Value::Player(text.to_string(), player)
+            }
+            ArgumentKind::World => {
+                let Some(world) = server.worlds.lock().await.get(text).cloned() else {
+                    return Err(format!("World \"{text}\" doesn't exist"))
+                };
+                Value::World(world)
+            }
+            ArgumentKind::Integer => Value::Integer(
+                text.parse().map_err(|err| format!("Invalid {name} \"{text}\": {err}"))?
+            ),
+            ArgumentKind::Decimal => Value::Decimal(
+                text.parse().map_err(|err| format!("Invalid {name} \"{text}\": {err}"))?
+            ),
+            ArgumentKind::Block => Value::Block(
+                text.parse().map_err(|err| format!("Invalid block ID \"{text}\": {err}"))?
+            ),
+            ArgumentKind::Coordinates => {
+                let (Some(y), Some(z)) = (words.next(), words.next()) else {
+                    return Err(format!("Expected three coordinates for {name}"))
+                };
+                let [x, y, z] = [text, y, z].map(str::parse::<i32>);
+                let (Ok(x), Ok(y), Ok(z)) = (x, y, z) else {
+                    return Err(format!("Coordinates of {name} must be whole numbers"))
+                };
+                Value::Coordinates(Vector3 { x, y, z })
+            }
+            ArgumentKind::Choice(choices) => {
+                if !choices.contains(&text) {
+                    return Err(format!("Invalid {name} \"{text}\". Valid options are {}", choices.join(", ")))
+                }
+                Value::Text(text.to_string())
+            }
+            ArgumentKind::Username | ArgumentKind::Word | ArgumentKind::Rest => Value::Text(text.to_string()),
+        };
+        values.push((name, value));
+    }
+    if let Some(extra) = words.next() {
+        return Err(format!("Unexpected argument \"{extra}\". See /help {path}"))
+    }
+    Ok(Arguments { values })
+}
+
+/// Takes the rest of the line, leaving nothing for later arguments.
+fn rest<'a>(words: &mut SplitAsciiWhitespace<'a>) -> Option<&'a str> {
+    let rest = words.remainder();
+    words.for_each(drop);
+    rest
+}
+
+async fn world_join(ctx: Context) -> CommandResult {
+    let world = ctx.args.world("world")?.clone();
+    ctx.player()?.send_to(world).await;
+    Ok(false)
+}
+
+async fn world_list(ctx: Context) -> CommandResult {
+    ctx.reply("&6[&eWorld List&6]").await;
+    let worlds = {
+        let lock = ctx.server.worlds.lock().await;
+        lock.keys().cloned().collect_vec()
+    };
+    for world in worlds {
+        ctx.reply(format!("- {world}")).await;
+    }
+    Ok(false)
+}
+
+async fn world_spawnpoint(ctx: Context) -> CommandResult {
+    let world = ctx.world()?;
+    let Some(location) = ctx.player()?.location.upgrade().map(|v| (&*v).into())
+        else { return Ok(false) };
+    world.data.lock().await.spawn_point = location;
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_spawnpoint().await;
+    }
+    ctx.reply("&3[&b#&3] &fSet world spawn to current location").await;
+    Ok(false)
+}
+
+async fn world_rename(ctx: Context) -> CommandResult {
+    let world_name = ctx.args.text("name")?;
+    let world = ctx.world()?;
+    {
+        // Lock the world list before the world, in the same order as saving on shutdown
+        let mut worlds = ctx.server.worlds.lock().await;
+        if worlds.contains_key(world_name) {
+            return Err(format!("World \"{world_name}\" already exists"))
+        }
+        let mut data = world.data.lock().await;
+        worlds.remove(&data.name);
+        worlds.insert(world_name.to_string(), world.clone());
+        data.name = world_name.to_string();
+    }
+    // Players are grouped by their world's name in the player list
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        ctx.server.update_tab_list(&player).await;
+    }
+    ctx.reply(format!("&3[&b#&3] &fRenamed world to \"{world_name}\".")).await;
+    Ok(false)
+}
+
+async fn world_save(ctx: Context) -> CommandResult {
+    let world = ctx.world()?;
+    let server = &ctx.server;
+    let name = world.data.lock().await.name.clone();
+    server.send_status(2, format!("&6[&e@&6] &fSaving world \"{name}\"...")).await;
+    let new_file = world.filepath.get().is_none();
+    if let Err(err) = world.clone().save().await {
+        server.send_message("&4[&c!&4] Failed to save! See logs for details.").await;
+        warn!("Failed to save world \"{name}\": {err}");
+        return Ok(false);
+    };
+    info!("Saved world \"{name}\"");
+    server.send_status(2, "&6[&e@&6] &fWorld saved!").await;
+    // Clear the status line after a few seconds
+    let status_server = server.clone();
+    tokio::spawn(async move {
+        time::sleep(Duration::from_secs(5)).await;
+        status_server.send_status(2, "").await;
+    });
+    ctx.reply(format!("&3[&b#&3] &fSaved world \"{name}\"")).await;
+    if new_file {
+        ctx.reply(format!(
+            "&2[&b#&2] &fSaved to \"{}\".",
+            world.filepath.get()
+                .and_then(|f| f.file_name())
+                .expect(".save() should initialize the filepath")
+                .to_string_lossy()
+        )).await;
+    }
+    Ok(false)
+}
+
+async fn world_create(ctx: Context) -> CommandResult {
+    // Only players can be sent to the new world, so don't generate it for anyone else
+    let player = ctx.player()?;
+    let dimension = |name| {
+        let value = ctx.args.integer(name)?;
+        u16::try_from(value).ok()
+            .filter(|&value| value > 0)
+            .ok_or(format!("Invalid {name} {value}: must be between 1 and {}", u16::MAX))
+    };
+    let (length, width, height) = (dimension("length")?, dimension("width")?, dimension("height")?);
+    let volume = u64::from(length) * u64::from(width) * u64::from(height);
+    if volume > MAX_WORLD_VOLUME {
+        return Err(format!("Worlds can't have more than {MAX_WORLD_VOLUME} blocks ({length}x{width}x{height} has {volume})"))
+    }
+    let generator = ctx.args.text("generator")?;
+    let seed: u64 = match ctx.args.text("seed") {
+        Ok(seed) => seed.parse().unwrap_or_else(
+            |_| fxhash::hash64(seed.as_bytes())
+        ),
+        Err(_) => rand::random()
+    };
+    let dimensions = Vector3 { x: length, z: width, y: height };
+
+    let world;
+    {
+        let lock = ctx.server.generators.lock();
+        let Some(generator) = lock.get(generator).map(|v| &**v) else { return Err(format!("Invalid generator {generator}"))};
+        let data = generator.generate(dimensions, seed)
+            .map_err(|err| format!("Generation error: {err}"))?;
+        world = World::from_data(WorldData {
+            level_data: LevelData {
+                raw_data: data,
+                dimensions
+            },
+            spawn_point: Location {
+                position: Vector3 {
+                    x: x32::from_num(length) / 2,
+                    y: x32::from_num(height),
+                    z: x32::from_num(width) / 2
+                },
+                yaw: 0,
+                pitch: 0
+            },
+            name: format!("<tmp-{}>", Uuid::new_v4()),
+            permissions: WorldPermissions {
+                owner: Some(ctx.username()),
+                ..WorldPermissions::default()
+            },
+            ..WorldData::default()
+        }, None);
+    }
+    player.send_to(world).await;
+    ctx.reply("&3[&b#&3] &fWorld generated!").await;
+    ctx.reply("&3[&b#&3] &fBe sure to &b/world rename&f and &b/world save&f.").await;
+    Ok(false)
+}
+
+async fn world_env(ctx: Context) -> CommandResult {
+    let property = ctx.args.text("property")?;
+    let value = ctx.args.text("value")?;
+    let world = ctx.world()?;
+    {
+        let mut lock = world.data.lock().await;
+        edit_environment(&mut lock.environment, property, value)?;
+    }
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_environment().await;
+    }
+    ctx.reply(format!("&3[&b#&3] &fSet {property} to {value}")).await;
+    Ok(false)
+}
+
+async fn world_hacks(ctx: Context) -> CommandResult {
+    let hacks = ctx.world()?.data.lock().await.hacks;
+    let flag = |allowed: bool| if allowed { "&aallowed" } else { "&cdenied" };
+    ctx.reply("&6[&eWorld Hacks&6]").await;
+    ctx.reply(format!("- Flying: {}", flag(hacks.flying))).await;
+    ctx.reply(format!("- Noclip: {}", flag(hacks.noclip))).await;
+    ctx.reply(format!("- Speed: {}", flag(hacks.speeding))).await;
+    ctx.reply(format!("- Respawn: {}", flag(hacks.respawn))).await;
+    ctx.reply(format!("- Third person: {}", flag(hacks.third_person))).await;
+    ctx.reply(format!("- Operator bypass: {}", flag(hacks.operator_bypass))).await;
+    if let Some(height) = hacks.jump_height {
+        ctx.reply(format!("- Jump height: {} blocks", f32::from(height) / 32.0)).await;
+    }
+    Ok(false)
+}
+
+async fn world_hacks_set(ctx: Context) -> CommandResult {
+    let hack = ctx.args.text("hack")?;
+    let value = ctx.args.text("value")?;
+    let world = ctx.world()?;
+    {
+        let mut lock = world.data.lock().await;
+        edit_hacks(&mut lock.hacks, hack, value)?;
+    }
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_hacks().await;
+    }
+    ctx.reply(format!("&3[&b#&3] &fSet {hack} to {value}")).await;
+    Ok(false)
+}
+
+async fn world_reach(ctx: Context) -> CommandResult {
+    let reach = ctx.world()?.data.lock().await.reach.unwrap_or(DEFAULT_REACH);
+    ctx.reply(format!("&3[&b#&3] &fReach: {} blocks", f32::from(reach) / 32.0)).await;
+    Ok(false)
+}
+
+async fn world_reach_set(ctx: Context) -> CommandResult {
+    let value = ctx.args.text("blocks")?;
+    let world = ctx.world()?;
+    let reach = if value == "reset" {
+        None
+    } else {
+        let blocks: f32 = value.parse().map_err(|err| format!("Invalid reach \"{value}\": {err}"))?;
+        if !(0.0..=1000.0).contains(&blocks) {
+            return Err("Reach must be between 0 and 1000 blocks".into())
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        Some((blocks * 32.0).round() as i16)
+    };
+    world.data.lock().await.reach = reach;
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_reach().await;
+    }
+    ctx.reply(format!("&3[&b#&3] &fSet reach to {value}")).await;
+    Ok(false)
+}
+
+async fn world_hotkey(ctx: Context) -> CommandResult {
+    let hotkeys = ctx.world()?.data.lock().await.hotkeys.clone();
+    ctx.reply("&6[&eWorld Hotkeys&6]").await;
+    for hotkey in hotkeys {
+        let key = hotkey_name(hotkey.key_code, hotkey.modifiers);
+        ctx.reply(format!("- {key}: {}", hotkey.action.trim_end_matches('◙'))).await;
+    }
+    Ok(false)
+}
+
+async fn world_hotkey_bind(ctx: Context) -> CommandResult {
+    let key = ctx.args.text("key")?;
+    let (key_code, modifiers) = parse_hotkey(key)?;
+    let action = ctx.args.text("action")?;
+    let world = ctx.world()?;
+    // Commands are run as soon as the key is pressed, other text is put in the chat box
+    let action = if action.starts_with('/') { format!("{action}◙") } else { action.to_string() };
+    if action.chars().count() > 64 {
+        return Err("Hotkey actions must fit in 64 characters".into())
+    }
+    if let Err(Cp437Error { representable_up_to }) = action.to_cp437(&CP437_WINGDINGS) {
+        return Err(format!("Invalid character at position {representable_up_to}"))
+    }
+    let hotkey = HotKey { action, key_code, modifiers };
+    {
+        // Binding a key again replaces its old action, on both the server and clients
+        let mut lock = world.data.lock().await;
+        match lock.hotkeys.iter_mut().find(|bound| bound.key_code == key_code && bound.modifiers == modifiers) {
+            Some(bound) => *bound = hotkey.clone(),
+            None => lock.hotkeys.push(hotkey.clone()),
+        }
+    }
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_hotkey(hotkey.clone(), true).await;
+    }
+    ctx.reply(format!("&3[&b#&3] &fBound {key}")).await;
+    Ok(false)
+}
+
+async fn world_hotkey_unbind(ctx: Context) -> CommandResult {
+    let key = ctx.args.text("key")?;
+    let (key_code, modifiers) = parse_hotkey(key)?;
+    let world = ctx.world()?;
+    let removed = {
+        let mut lock = world.data.lock().await;
+        let index = lock.hotkeys.iter().position(|bound| bound.key_code == key_code && bound.modifiers == modifiers);
+        index.map(|index| lock.hotkeys.remove(index))
+    };
+    let Some(hotkey) = removed else {
+        return Err(format!("{key} isn't bound"))
+    };
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_hotkey(hotkey.clone(), false).await;
+    }
+    ctx.reply(format!("&3[&b#&3] &fUnbound {key}")).await;
+    Ok(false)
+}
+
+async fn world_palette(ctx: Context) -> CommandResult {
+    let palette = ctx.world()?.data.lock().await.palette.clone();
+    if palette.is_empty() {
+        ctx.reply("&3[&b#&3] &fThis world uses the default inventory").await;
+    } else {
+        ctx.reply(format!("&3[&b#&3] &fPalette: {}", palette.iter().join(", "))).await;
+    }
+    Ok(false)
+}
+
+async fn world_palette_set(ctx: Context) -> CommandResult {
+    let blocks = parse_block_list(ctx.args.text("blocks")?.split_ascii_whitespace())?;
+    edit_palette(&ctx, |palette| *palette = blocks).await
+}
+
+async fn world_palette_add(ctx: Context) -> CommandResult {
+    let blocks = parse_block_list(ctx.args.text("blocks")?.split_ascii_whitespace())?;
+    edit_palette(&ctx, |palette| palette.extend(blocks.into_iter().filter(|id| !palette.contains(id)).collect_vec())).await
+}
+
+async fn world_palette_remove(ctx: Context) -> CommandResult {
+    let blocks = parse_block_list(ctx.args.text("blocks")?.split_ascii_whitespace())?;
+    edit_palette(&ctx, |palette| palette.retain(|id| !blocks.contains(id))).await
+}
+
+async fn world_palette_reset(ctx: Context) -> CommandResult {
+    edit_palette(&ctx, Vec::clear).await
+}
+
+/// Changes the palette of the sender's world, and reorders the inventories of everyone in it.
+async fn edit_palette(ctx: &Context, edit: impl FnOnce(&mut Vec<u8>)) -> CommandResult {
+    let world = ctx.world()?;
+    {
+        let palette = &mut world.data.lock().await.palette;
+        edit(palette);
+        blocks::clean_palette(palette);
+    }
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_palette().await;
+    }
+    ctx.reply("&3[&b#&3] &fUpdated the world's palette").await;
+    Ok(false)
+}
+
+async fn world_perms(ctx: Context) -> CommandResult {
+    let world = ctx.world()?;
+    let Ok(property) = ctx.args.text("property") else {
+        let permissions = world.data.lock().await.permissions.clone();
+        ctx.reply("&6[&eWorld Permissions&6]").await;
+        ctx.reply(format!("- Owner: {}", permissions.owner.as_deref().unwrap_or("none"))).await;
+        if !permissions.builders.is_empty() {
+            ctx.reply(format!("- Builders: {}", permissions.builders.iter().join(", "))).await;
+        }
+        let [visit, build] = [permissions.visit, permissions.build]
+            .map(|minimum| describe_requirement(&ctx.server, minimum));
+        ctx.reply(format!("- Visit: {visit}")).await;
+        ctx.reply(format!("- Build: {build}")).await;
+        return Ok(false);
+    };
+    let mut values = ctx.args.text("value").unwrap_or_default().split_ascii_whitespace();
+    let username = ctx.username();
+    let mut lock = world.data.lock().await;
+    let permissions = &mut lock.permissions;
+    if !permissions.can_edit(&username, ctx.rank.has(ranks::WORLD_EDIT)) {
+        return Err("Only the world's owner can change its permissions".into())
+    }
+    let mut changed_builders = None;
+    match property {
+        "owner" => {
+            let Some(owner) = values.next() else { return Err("No owner specified".into()) };
+            permissions.owner = (owner != "none").then(|| owner.to_string());
+        }
+        "builders" => {
+            let Some(action) = values.next() else { return Err("No action specified".into()) };
+            let names = values.map(str::to_string).collect_vec();
+            if names.is_empty() {
+                return Err("No players specified".into())
+            }
+            // Report which names actually changed, so typos are visible
+            changed_builders = Some(match action {
+                "add" => ("Added", names.into_iter().filter(|name| permissions.builders.insert(name.clone())).collect_vec()),
+                "remove" => ("Removed", names.into_iter().filter(|name| permissions.builders.remove(name)).collect_vec()),
+                _ => return Err(format!("Invalid action \"{action}\". Valid actions are add and remove"))
+            });
+        }
+        _ => {
+            let Some(name) = values.next() else { return Err("No rank specified".into()) };
+            let minimum = parse_requirement(&ctx.server, name)?;
+            if property == "visit" {
+                if minimum != RankRequirement::default() && Arc::ptr_eq(&world.data, &ctx.server.default_world.data) {
+                    return Err("Everyone must be able to visit the default world".into())
+                }
+                permissions.visit = minimum;
+            } else {
+                permissions.build = minimum;
+            }
+        }
+    }
+    drop(lock);
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_block_access().await;
+    }
+    match changed_builders {
+        Some((_, names)) if names.is_empty() => ctx.reply("&3[&b#&3] &fNo builders were changed").await,
+        Some((verb, names)) => ctx.reply(format!("&3[&b#&3] &f{verb} builders: {}", names.join(", "))).await,
+        None => ctx.reply("&3[&b#&3] &fUpdated the world's permissions").await,
+    }
+    Ok(false)
+}
+
+async fn world_access(ctx: Context) -> CommandResult {
+    let overrides = ctx.world()?.data.lock().await.block_access.clone();
+    if overrides.is_empty() {
+        ctx.reply("&3[&b#&3] &fThis world uses the default block access").await;
+        return Ok(false);
+    }
+    ctx.reply("&6[&eBlock Access&6]").await;
+    for (id, access) in overrides {
+        ctx.reply(format!("- {id}: {}", describe_requirement(&ctx.server, access))).await;
+    }
+    Ok(false)
+}
+
+async fn world_access_set(ctx: Context) -> CommandResult {
+    let rank = ctx.args.text("rank")?;
+    let access = parse_requirement(&ctx.server, rank)?;
+    edit_block_access(&ctx, Some(access)).await
+}
+
+async fn world_access_reset(ctx: Context) -> CommandResult {
+    edit_block_access(&ctx, None).await
+}
+
+/// Changes who can place and delete the blocks given to a command, or resets them to their default if `access` is `None`.
+async fn edit_block_access(ctx: &Context, access: Option<RankRequirement>) -> CommandResult {
+    let blocks = parse_block_list(ctx.args.text("blocks")?.split_ascii_whitespace())?;
+    if blocks.is_empty() {
+        return Err("No blocks specified".into());
+    }
+    let world = ctx.world()?;
+    {
+        let mut lock = world.data.lock().await;
+        for id in blocks {
+            match access {
+                Some(access) if access != RankRequirement::default_for_block(id) => { lock.block_access.insert(id, access); }
+                _ => { lock.block_access.remove(&id); }
+            }
+        }
+    }
+    let players: Vec<_> = world.players.lock().values().cloned().collect();
+    for player in players {
+        player.notify_block_access().await;
+    }
+    ctx.reply("&3[&b#&3] &fUpdated the world's block access").await;
+    Ok(false)
+}
+
+async fn world_generators(ctx: Context) -> CommandResult {
+    ctx.reply("&6[&eWorld Generators&6]").await;
+    let keys: Vec<_> = {
+        let lock = ctx.server.generators.lock();
+        lock.keys().cloned().collect()
+    };
+    for gen in keys {
+        ctx.reply(format!("- {gen}")).await;
+    }
+    Ok(false)
+}
+
+async fn block_list(ctx: Context) -> CommandResult {
+    let world = ctx.world()?;
+    let definitions: Vec<_> = {
+        let lock = world.data.lock().await;
+        lock.block_definitions.iter()
+            .map(|(id, definition)| format!("- {id}: {} (fallback {})", definition.name, definition.fallback))
+            .collect()
+    };
+    ctx.reply("&6[&eBlock List&6]").await;
+    for line in definitions {
+        ctx.reply(line).await;
+    }
+    Ok(false)
+}
+
+async fn block_define(ctx: Context) -> CommandResult {
+    let id = not_air(ctx.args.block("id")?)?;
+    let fallback = not_air(ctx.args.block("fallback")?)?;
+    if fallback > blocks::MAX_CUSTOM_BLOCK {
+        return Err(format!("Fallback must be a built-in block (at most {})", blocks::MAX_CUSTOM_BLOCK));
+    }
+    let name = ctx.args.text("name")?;
+    let definition = BlockDefinition::new(name.to_string(), 1, fallback);
+    ctx.player()?.update_block_definition(id, Some(definition)).await;
+    ctx.reply(format!("&3[&b#&3] &fDefined block {id} as \"{name}\"")).await;
+    Ok(false)
+}
+
+async fn block_edit(ctx: Context) -> CommandResult {
+    let id = not_air(ctx.args.block("id")?)?;
+    let property = ctx.args.text("property")?;
+    let value = ctx.args.text("value")?;
+    let world = ctx.world()?;
+    let mut definition = {
+        let lock = world.data.lock().await;
+        lock.block_definitions.get(&id).cloned()
+            .ok_or(format!("Block {id} is not defined"))?
+    };
+    edit_block_definition(&mut definition, property, value)?;
+    ctx.player()?.update_block_definition(id, Some(definition)).await;
+    ctx.reply(format!("&3[&b#&3] &fSet {property} of block {id} to {value}")).await;
+    Ok(false)
+}
+
+async fn block_remove(ctx: Context) -> CommandResult {
+    let id = not_air(ctx.args.block("id")?)?;
+    ctx.player()?.update_block_definition(id, None).await;
+    ctx.reply(format!("&3[&b#&3] &fRemoved definition of block {id}")).await;
+    Ok(false)
+}
+
+async fn select(ctx: Context) -> CommandResult {
+    let Ok(start) = ctx.args.coordinates("start") else {
+        let Some(selection) = ctx.player()?.selection(PLAYER_SELECTION) else {
+            return Err("Nothing is selected".into())
+        };
+        let (start, end, size) = (selection.start(), selection.end(), selection.size());
+        ctx.reply(format!(
+            "&3[&b#&3] &fSelected {}, {}, {} to {}, {}, {} ({}x{}x{})",
+            start.x, start.y, start.z,
+            end.x, end.y, end.z,
+            size.x, size.y, size.z
+        )).await;
+        return Ok(false);
+    };
+    let end = ctx.args.coordinates("end")?;
+    let position = |coordinates: Vector3<i32>| {
+        let [x, y, z] = [coordinates.x, coordinates.y, coordinates.z].map(u16::try_from);
+        let (Ok(x), Ok(y), Ok(z)) = (x, y, z) else {
+            return Err("Coordinates must be positive whole numbers".to_string())
+        };
+        Ok(Vector3 { x, y, z })
+    };
+    let (start, end) = (position(start)?, position(end)?);
+    ctx.player()?.select(PLAYER_SELECTION, Selection::new("Selection".into(), start, end)).await;
+    Ok(false)
+}
+
+async fn select_pos1(ctx: Context) -> CommandResult {
+    select_corner(&ctx, true).await?;
+    Ok(false)
+}
+
+async fn select_pos2(ctx: Context) -> CommandResult {
+    select_corner(&ctx, false).await?;
+    Ok(false)
+}
+
+/// Moves a corner of the player's selection to the block they're standing in.
+async fn select_corner(ctx: &Context, first: bool) -> Result<(), String> {
+    let position = ctx.player()?.block_position().await?;
+    let (first_corner, second_corner) = match ctx.player()?.selection(PLAYER_SELECTION) {
+        Some(Selection { second, .. }) if first => (position, second),
+        Some(Selection { first, .. }) => (first, position),
+        None => (position, position)
+    };
+    ctx.player()?.select(PLAYER_SELECTION, Selection::new("Selection".into(), first_corner, second_corner)).await;
+    Ok(())
+}
+
+async fn select_clear(ctx: Context) -> CommandResult {
+    ctx.player()?.remove_selection(PLAYER_SELECTION).await;
+    ctx.reply("&3[&b#&3] &fCleared selection").await;
+    Ok(false)
+}
+
+async fn hold(ctx: Context) -> CommandResult {
+    let Ok(block) = ctx.args.block("block") else {
+        let Some(block) = ctx.player()?.held_block() else {
+            return Err("Your client doesn't support held blocks".into())
+        };
+        ctx.reply(format!("&3[&b#&3] &fYou are holding block {block}")).await;
+        return Ok(false);
+    };
+    if block > ctx.player()?.max_block() {
+        return Err(format!("Your client doesn't support block {block}"))
+    }
+    if let Ok(slot) = ctx.args.integer("slot") {
+        let slot = u8::try_from(slot).ok()
+            .filter(|slot| (1..=9).contains(slot))
+            .ok_or("Hotbar slot must be between 1 and 9")?;
+        if !ctx.player()?.extensions().contains(Extension::SetHotbar) {
+            return Err("Your client doesn't support changing the hotbar".into())
+        }
+        ctx.player()?.set_hotbar(block, slot - 1).await;
+    } else {
+        if !ctx.player()?.extensions().contains(Extension::HeldBlock) {
+            return Err("Your client doesn't support held blocks".into())
+        }
+        ctx.player()?.hold_block(block, false).await;
+    }
+    Ok(false)
+}
+
+async fn fill(ctx: Context) -> CommandResult {
+    let block = ctx.args.block("block")?;
+    let player = ctx.player()?;
+    let Some(selection) = player.selection(PLAYER_SELECTION) else {
+        return Err("Nothing is selected, use /select first".into())
+    };
+    let (start, end) = (selection.start(), selection.end());
+    let world = ctx.world()?;
+    let username = ctx.username();
+    let level = ctx.rank.level;
+    let changes = {
+        let lock = world.data.lock().await;
+        if !lock.permissions.can_build(&username, level) {
+            return Err(BlockRejection::CantBuild.to_string())
+        }
+        lock.check_block_id(block, player.max_block()).map_err(|rejection| rejection.to_string())?;
+        if block != 0 && !lock.can_use_block(block, level) {
+            return Err(BlockRejection::CantPlace(block).to_string())
+        }
+
+        let dimensions = lock.level_data.dimensions;
+        if start.x >= dimensions.x || start.y >= dimensions.y || start.z >= dimensions.z {
+            return Err("Your selection is outside of the world".into())
+        }
+        let end = Vector3 {
+            x: end.x.min(dimensions.x - 1),
+            y: end.y.min(dimensions.y - 1),
+            z: end.z.min(dimensions.z - 1),
+        };
+        let volume = [(start.x, end.x), (start.y, end.y), (start.z, end.z)].into_iter()
+            .map(|(start, end)| usize::from(end - start) + 1)
+            .product::<usize>();
+        if volume > MAX_FILL_VOLUME {
+            return Err(format!("You can't fill more than {MAX_FILL_VOLUME} blocks at once (selected {volume})"))
+        }
+
+        // Blocks the player can't delete are left alone, but liquids can always be built over
+        (start.y..=end.y)
+            .cartesian_product(start.z..=end.z)
+            .cartesian_product(start.x..=end.x)
+            .map(|((y, z), x)| Vector3 { x, y, z })
+            .filter(|&position| lock.level_data.get(position).is_some_and(|original| {
+                original == 0 || (block != 0 && matches!(original, 8..=11)) || lock.can_use_block(original, level)
+            }))
+            .map(|position| (position, block))
+            .collect_vec()
+    };
+    let count = world.set_blocks(changes).await;
+    ctx.reply(format!("&3[&b#&3] &fChanged {count} blocks")).await;
+    Ok(false)
+}
+
+async fn announce(ctx: Context) -> CommandResult {
+    ctx.server.send_announcement(ctx.args.text("message")?).await;
+    Ok(false)
+}
+
+async fn stop(ctx: Context) -> CommandResult {
+    ctx.server.stop().await;
+    Ok(true)
+}
+
+async fn whisper(ctx: Context) -> CommandResult {
+    let (name, player) = ctx.args.player("player")?;
+    let message = ctx.args.text("message")?;
+    let own_name = ctx.username();
+    ctx.reply(format!("&0[&8{own_name} -> {name}&0] &7{message}")).await;
+    player.send_message(format!("&0[&8{own_name} -> {name}&0] &7{message}")).await;
+    Ok(false)
+}
+
+async fn locate(ctx: Context) -> CommandResult {
+    if let Ok((name, player)) = ctx.args.player("player") {
+        let Some(world) = player.world.upgrade() else {
+            return Err(format!("User {name} is not online"))
+        };
+        let world = world.lock().clone();
+        ctx.reply(format!("{name} is in \"{}\"", world.data.lock().await.name)).await;
+    } else {
+        let world = ctx.world()?;
+        ctx.reply(format!("You are in \"{}\"", world.data.lock().await.name)).await;
+    }
+    Ok(false)
+}
+
+async fn ping(ctx: Context) -> CommandResult {
+    let (name, player) = ctx.player_or_self("player")?;
+    if !player.extensions().contains(Extension::TwoWayPing) {
+        return Err(format!("{name}'s client doesn't support measuring ping"))
+    }
+    let Some((min, average, max)) = player.latency() else {
+        return Err(format!("{name}'s ping hasn't been measured yet"))
+    };
+    ctx.reply(format!(
+        "&3[&b#&3] &f{name}'s ping: {}ms average, {}ms min, {}ms max",
+        average.as_millis(), min.as_millis(), max.as_millis()
+    )).await;
+    Ok(false)
+}
+
+async fn extensions(ctx: Context) -> CommandResult {
+    let (name, player) = ctx.player_or_self("player")?;
+    let extensions = player.extensions();
+    let negotiated = Extension::ALL.iter()
+        .filter_map(|&extension| Some(format!("{} v{}", extension.info().name, extensions.version(extension)?)))
+        .join(", ");
+    if negotiated.is_empty() {
+        return Err(format!("{name}'s client doesn't support any extensions"))
+    }
+    ctx.reply(format!("&3[&b#&3] &f{name}'s extensions: {negotiated}")).await;
+    Ok(false)
+}
+
+async fn players(ctx: Context) -> CommandResult {
+    let names = ctx.server.connected_players.lock().await.keys().cloned().collect_vec();
+    ctx.reply("&3[&bPlayer List&3]").await;
+    for name in names {
+        ctx.reply(name).await;
+    }
+    Ok(false)
+}
+
+async fn rank(ctx: Context) -> CommandResult {
+    let Ok(name) = ctx.args.text("player") else {
+        ctx.reply(format!("&3[&b#&3] &fYour rank is {}", ctx.rank.display_name())).await;
+        return Ok(false);
+    };
+    let rank = ctx.server.config.lock().rank_of(name).display_name();
+    ctx.reply(format!("&3[&b#&3] &f{name}'s rank is {rank}")).await;
+    Ok(false)
+}
+
+async fn rank_set(ctx: Context) -> CommandResult {
+    let name = ctx.args.text("player")?;
+    let rank = ctx.args.text("rank")?;
+    set_rank(&ctx, name, rank).await?;
+    Ok(false)
+}
+
+async fn list_ranks(ctx: Context) -> CommandResult {
+    let ranks = ctx.server.config.lock().ranks.clone();
+    ctx.reply("&6[&eRanks&6]").await;
+    for rank in ranks.iter().rev() {
+        ctx.reply(format!("- {} &f(level {})", rank.display_name(), rank.level)).await;
+    }
+    Ok(false)
+}
+
+async fn op(ctx: Context) -> CommandResult {
+    let name = ctx.args.text("player")?;
+    let highest = ctx.server.config.lock().highest_rank().name.clone();
+    set_rank(&ctx, name, &highest).await?;
+    Ok(false)
+}
+
+async fn deop(ctx: Context) -> CommandResult {
+    let name = ctx.args.text("player")?;
+    let lowest = ctx.server.config.lock().lowest_rank().name.clone();
+    set_rank(&ctx, name, &lowest).await?;
+    Ok(false)
+}
+
+/// Changes the rank of a player, as long as their current rank and their new one are no higher than the sender's.
+async fn set_rank(ctx: &Context, name: &str, rank_name: &str) -> Result<(), String> {
+    let rank = {
+        let mut conf = ctx.server.config.lock();
+        let Some(rank) = conf.rank(rank_name).cloned() else {
+            return Err(format!("Rank \"{rank_name}\" doesn't exist. See /ranks"))
+        };
+        if rank.level > ctx.rank.level || conf.rank_of(name).level > ctx.rank.level {
+            return Err("You can't change ranks above your own".into())
+        }
+        if rank.name == conf.lowest_rank().name {
+            conf.player_ranks.remove(name);
+        } else {
+            conf.player_ranks.insert(name.to_string(), rank.name.clone());
+        }
+        rank
+    };
+
+    let player = ctx.server.connected_players.lock().await.get(name).cloned();
+    if let Some(player) = player {
+        player.send_message(format!("&3[&b#&3] &fYour rank is now {}", rank.display_name())).await;
+        player.notify_rank(rank.level, rank.has(ranks::OPERATOR)).await;
+        ctx.server.update_tab_list(&player).await;
+    }
+
+    ctx.reply(format!("&3[&b#&3] &fSet {name}'s rank to {}", rank.display_name())).await;
+    Ok(())
+}
+
+async fn model(ctx: Context) -> CommandResult {
+    let (name, player) = ctx.args.player("player")?;
+    let model = ctx.args.text("model")?.to_ascii_lowercase();
+    if !Model::NAMES.contains(&model.as_str()) && model.parse::<u8>().is_err() {
+        return Err(format!(
+            "Invalid model \"{model}\". Valid models are block IDs and {}", Model::NAMES.join(", ")
+        ))
+    }
+    player.update_model(|current| current.name.clone_from(&model)).await;
+    ctx.reply(format!("&3[&b#&3] &fSet {name}'s model to {model}")).await;
+    Ok(false)
+}
+
+async fn scale(ctx: Context) -> CommandResult {
+    let (name, player) = ctx.args.player("player")?;
+    let scale = ctx.args.decimal("scale")?;
+    if !(0.25..=4.0).contains(&scale) {
+        return Err("Scale must be between 0.25 and 4".into())
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let thousandths = (scale * 1000.0).round() as i32;
+    player.update_model(|current| current.scale = thousandths).await;
+    ctx.reply(format!("&3[&b#&3] &fSet {name}'s scale to {scale}")).await;
+    Ok(false)
+}
+
+async fn launch(ctx: Context) -> CommandResult {
+    let (name, player) = ctx.args.player("player")?;
+    let mut velocity = [0; 3];
+    for (axis, name) in velocity.iter_mut().zip(["x", "y", "z"]) {
+        let value = ctx.args.decimal(name)?;
+        if !(-1024.0..=1024.0).contains(&value) {
+            return Err("Velocity must be between -1024 and 1024 blocks per tick".into())
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let value = (value * 10000.0).round() as i32;
+        *axis = value;
+    }
+    let replace = ctx.args.text("mode") == Ok("set");
+    if !player.extensions().contains(Extension::VelocityControl) {
+        return Err(format!("{name}'s client doesn't support launching"))
+    }
+    player.launch(Vector3::from(velocity), [replace; 3]).await;
+    ctx.reply(format!("&3[&b#&3] &fLaunched {name}")).await;
+    Ok(false)
+}
+
+async fn kick(ctx: Context) -> CommandResult {
+    let (name, player) = ctx.args.player("player")?;
+    if ctx.server.config.lock().rank_of(name).level > ctx.rank.level {
+        return Err("You can't kick players ranked above you".into())
+    }
+    player.notify_disconnect(
+        format!("Kicked: {}", ctx.args.text("reason").unwrap_or("No reason given"))
+    ).await;
+    ctx.reply(format!("&3[&b#&3] &fKicked {name}")).await;
+    Ok(false)
+}
+
+async fn ban(ctx: Context) -> CommandResult {
+    let name = ctx.args.text("player")?;
+    let reason = ctx.args.text("reason").unwrap_or("No reason given");
+    {
+        let mut conf = ctx.server.config.lock();
+        if conf.rank_of(name).level > ctx.rank.level {
+            return Err("You can't ban players ranked above you".into())
+        }
+        conf.banned_users.insert(name.to_string(), reason.to_string());
+    }
+    let player = ctx.server.connected_players.lock().await.get(name).cloned();
+    if let Some(player) = player {
+        player.notify_disconnect(format!("Banned: {reason}")).await;
+    }
+    ctx.reply(format!("&3[&b#&3] &fBanned {name}")).await;
+    Ok(false)
+}
+
+async fn unban(ctx: Context) -> CommandResult {
+    let name = ctx.args.text("player")?;
+    {
+        let mut conf = ctx.server.config.lock();
+        if conf.rank_of(name).level > ctx.rank.level {
+            return Err("You can't unban players ranked above you".into())
+        }
+        conf.banned_users.remove(name);
+    }
+    ctx.reply(format!("&3[&b#&3] &fUnbanned {name}")).await;
+    Ok(false)
+}
+
+async fn help(ctx: Context) -> CommandResult {
+    let Ok(query) = ctx.args.text("command") else {
+        ctx.reply("&5[&dCommand List&5]").await;
+        let mut lines = Vec::new();
+        list_commands(COMMANDS, "", &ctx.rank, &mut lines);
+        for line in lines {
+            ctx.reply(line).await;
+        }
+        ctx.reply("See /help <command> for more details").await;
+        return Ok(false);
+    };
+
+    let Some((command, path)) = find_command(query.split_ascii_whitespace()) else {
+        return Err(format!("Invalid command {query}"))
+    };
+    if !command.allows(&ctx.rank) {
+        return Err(format!("You don't have permission to use /{path}"))
+    }
+    ctx.reply(format!("&5[&d/{path}&5]")).await;
+    ctx.reply(command.description).await;
+    if command.handler.is_some() {
+        ctx.reply(format!("Usage: {}", command.usage(&path))).await;
+    }
+    if !command.aliases.is_empty() {
+        ctx.reply(format!("Aliases: {}", command.aliases.iter().map(|alias| format!("/{alias}")).join(", "))).await;
+    }
+    if let Some(permission) = command.permission {
+        ctx.reply(format!("Permission: {permission}")).await;
+    }
+    for subcommand in command.subcommands.iter().filter(|subcommand| subcommand.allows(&ctx.rank)) {
+        let color = if subcommand.permission.is_some() { "&b" } else { "" };
+        ctx.reply(format!("{color}- {}", subcommand.usage(&format!("{path} {}", subcommand.name)))).await;
+        ctx.reply(format!("  &7{}", subcommand.description)).await;
+    }
+    Ok(false)
+}
+
+/// Lists the commands that a rank can use for `/help`, with subcommands indented under the command they belong to.
+/// Commands that need a permission are highlighted. `parent` is the full name of the command that they're subcommands of.
+fn list_commands(commands: &[Command], parent: &str, rank: &Rank, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(parent.split_ascii_whitespace().count());
+    for command in commands.iter().filter(|command| command.allows(rank)) {
+        let color = if command.permission.is_some() { "&b" } else { "" };
+        let path = if parent.is_empty() { command.name.to_string() } else { format!("{parent} {}", command.name) };
+        if command.handler.is_some() {
+            lines.push(format!("{color}{indent}- {}", command.usage(&path)));
+        } else {
+            lines.push(format!("{color}{indent}- /{path}"));
+        }
+        list_commands(command.subcommands, &path, rank, lines);
+    }
+}
+
+/// Describes a rank requirement by the lowest rank that meets it, like `&emoderator`,
+/// `level 60` if no rank does, or `nobody`.
+fn describe_requirement(server: &RunningServer, requirement: RankRequirement) -> String {
+    let RankRequirement::Level(level) = requirement else { return "nobody".into() };
+    let config = server.config.lock();
+    config.ranks.iter()
+        .find(|rank| rank.level >= level)
+        .map_or_else(|| format!("level {level}"), Rank::display_name)
+}
+
+/// Parses the name of a rank into a requirement for its level, or `nobody`.
+fn parse_requirement(server: &RunningServer, name: &str) -> Result<RankRequirement, String> {
+    if name == "nobody" {
+        return Ok(RankRequirement::Nobody);
+    }
+    let config = server.config.lock();
+    config.rank(name)
+        .map(|rank| RankRequirement::Level(rank.level))
+        .ok_or(format!("Rank \"{name}\" doesn't exist. Valid ranks are nobody and those in /ranks"))
+}
+
+/// Parses a block ID from a command argument.
+fn parse_block_id(argument: &str) -> Result<u8, String> {
+    let id: u8 = argument.parse().map_err(|err| format!("Invalid block ID: {err}"))?;
+    not_air(id)
+}
+
+/// Rejects air in commands that change block definitions and access.
+fn not_air(id: u8) -> Result<u8, String> {
+    if id == 0 {
+        return Err("Cannot modify air".into());
+    }
+    Ok(id)
+}
+
+/// Parses a list of block IDs and ranges of block IDs, like `21-36 1 4`, from the rest of a command's arguments.
+fn parse_block_list<'a>(arguments: impl Iterator<Item = &'a str>) -> Result<Vec<u8>, String> {
+    let mut blocks = Vec::new();
+    for argument in arguments {
+        if let Some((start, end)) = argument.split_once('-') {
+            let start = parse_block_id(start)?;
+            let end = parse_block_id(end)?;
+            blocks.extend((start..=end).filter(|id| !blocks.contains(id)).collect_vec());
+        } else {
+            let id = parse_block_id(argument)?;
+            if !blocks.contains(&id) {
+                blocks.push(id);
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// Parses a hexadecimal RGB color from a command argument.
+fn parse_color(argument: &str) -> Result<[u8; 3], String> {
+    let hex = argument.strip_prefix('#').unwrap_or(argument);
+    let color = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or(format!("Invalid color \"{argument}\", expected a hex code like #ff8000"))?;
+    let [_, r, g, b] = color.to_be_bytes();
+    Ok([r, g, b])
+}
+
+/// Edits a single property of a block definition from a command.
+fn edit_block_definition(definition: &mut BlockDefinition, property: &str, value: &str) -> Result<(), String> {
+    fn number(value: &str) -> Result<u8, String> {
+        value.parse().map_err(|err| format!("Invalid value \"{value}\": {err}"))
+    }
+    fn flag(value: &str) -> Result<bool, String> {
+        match value {
+            "yes" | "true" | "on" => Ok(true),
+            "no" | "false" | "off" => Ok(false),
+            _ => Err(format!("Invalid value \"{value}\", expected yes or no"))
+        }
+    }
+    fn corner(value: &str) -> Result<Vector3<u8>, String> {
+        let Some((x, y, z)) = value.split_ascii_whitespace().map(number).collect_tuple() else {
+            return Err("Expected three numbers".into())
+        };
+        let corner = Vector3 { x: x?, y: y?, z: z? };
+        if corner.x > 16 || corner.y > 16 || corner.z > 16 {
+            return Err("Corners must be between 0 and 16".into())
+        }
+        Ok(corner)
+    }
+
+    match property {
+        "name" => value.clone_into(&mut definition.name),
+        "solidity" => definition.solidity = match value {
+            "walk" => 0,
+            "swim" => 1,
+            "solid" => 2,
+            _ => number(value)?
+        },
+        "speed" => definition.speed = number(value)?,
+        "texture" => definition.textures = [number(value)?; 6],
+        "top" => definition.textures[0] = number(value)?,
+        "side" => definition.textures[1..5].fill(number(value)?),
+        "left" => definition.textures[1] = number(value)?,
+        "right" => definition.textures[2] = number(value)?,
+        "front" => definition.textures[3] = number(value)?,
+        "back" => definition.textures[4] = number(value)?,
+        "bottom" => definition.textures[5] = number(value)?,
+        "light" => definition.transmits_light = flag(value)?,
+        "sound" => definition.walk_sound = number(value)?,
+        "bright" => definition.full_bright = flag(value)?,
+        "sprite" => definition.sprite = flag(value)?,
+        "min" => definition.min = corner(value)?,
+        "max" => definition.max = corner(value)?,
+        "draw" => definition.draw = number(value)?,
+        "fog" => {
+            let Some((density, color)) = value.split_ascii_whitespace().collect_tuple() else {
+                return Err("Expected a fog density and a color".into())
+            };
+            definition.fog_density = number(density)?;
+            definition.fog_color = parse_color(color)?;
+        }
+        "fallback" => {
+            let fallback = number(value)?;
+            if fallback > blocks::MAX_CUSTOM_BLOCK {
+                return Err(format!("Fallback must be a built-in block (at most {})", blocks::MAX_CUSTOM_BLOCK));
+            }
+            definition.fallback = fallback;
+        }
+        _ => return Err(format!(
+            "Invalid property \"{property}\". Valid properties are name, solidity, speed, texture, top, side, \
+            left, right, front, back, bottom, light, sound, bright, sprite, min, max, draw, fog, and fallback"
+        ))
+    }
+    Ok(())
+}
+
+/// Edits a single property of a world's environment from a command.
+fn edit_environment(environment: &mut Environment, property: &str, value: &str) -> Result<(), String> {
+    let reset = value == "reset";
+    if let Some(index) = Environment::COLOR_NAMES.iter().position(|name| *name == property) {
+        environment.colors[index] = if reset { None } else { Some(parse_color(value)?) };
+        return Ok(());
+    }
+    match property {
+        "side" | "edge" => {
+            let block = if reset {
+                None
+            } else {
+                Some(value.parse().map_err(|err| format!("Invalid block ID \"{value}\": {err}"))?)
+            };
+            if property == "side" {
+                environment.side_block = block;
+            } else {
+                environment.edge_block = block;
+            }
+        }
+        "water" => environment.water_level = if reset {
+            None
+        } else {
+            Some(value.parse().map_err(|err| format!("Invalid water level \"{value}\": {err}"))?)
+        },
+        "weather" => environment.weather = if reset {
+            0
+        } else {
+            Environment::WEATHER_NAMES.iter()
+                .position(|name| *name == value)
+                .and_then(|weather| u8::try_from(weather).ok())
+                .ok_or(format!("Invalid weather \"{value}\", expected sun, rain, or snow"))?
+        },
+        _ => return Err(format!(
+            "Invalid property \"{property}\". Valid properties are sky, cloud, fog, ambient, diffuse, side, edge, water, and weather"
+        ))
+    }
+    Ok(())
+}
+
+/// Edits a single setting of a world's hack policy from a command.
+fn edit_hacks(hacks: &mut HackPolicy, hack: &str, value: &str) -> Result<(), String> {
+    if hack == "jump" {
+        hacks.jump_height = if value == "reset" {
+            None
+        } else {
+            let blocks: f32 = value.parse().map_err(|err| format!("Invalid jump height \"{value}\": {err}"))?;
+            if !(0.0..=32.0).contains(&blocks) {
+                return Err("Jump height must be between 0 and 32 blocks".into())
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            Some((blocks * 32.0).round() as i16)
+        };
+        return Ok(());
+    }
+    let allowed = match value {
+        "on" | "yes" | "true" | "allow" => true,
+        "off" | "no" | "false" | "deny" => false,
+        _ => return Err(format!("Invalid value \"{value}\", expected on or off"))
+    };
+    match hack {
+        "fly" => hacks.flying = allowed,
+        "noclip" => hacks.noclip = allowed,
+        "speed" => hacks.speeding = allowed,
+        "respawn" => hacks.respawn = allowed,
+        "thirdperson" => hacks.third_person = allowed,
+        "ophax" => hacks.operator_bypass = allowed,
+        "all" => {
+            hacks.flying = allowed;
+            hacks.noclip = allowed;
+            hacks.speeding = allowed;
+            hacks.respawn = allowed;
+            hacks.third_person = allowed;
+        }
+        _ => return Err(format!(
+            "Invalid hack \"{hack}\". Valid hacks are fly, noclip, speed, respawn, thirdperson, ophax, all, and jump"
+        ))
+    }
+    Ok(())
+}
+
+/// The names of the keys that hotkeys can be bound to, with their LWJGL key codes.
+const KEY_NAMES: [(&str, i32); 48] = [
+    ("1", 2), ("2", 3), ("3", 4), ("4", 5), ("5", 6), ("6", 7), ("7", 8), ("8", 9), ("9", 10), ("0", 11),
+    ("q", 16), ("w", 17), ("e", 18), ("r", 19), ("t", 20), ("y", 21), ("u", 22), ("i", 23), ("o", 24), ("p", 25),
+    ("a", 30), ("s", 31), ("d", 32), ("f", 33), ("g", 34), ("h", 35), ("j", 36), ("k", 37), ("l", 38),
+    ("z", 44), ("x", 45), ("c", 46), ("v", 47), ("b", 48), ("n", 49), ("m", 50),
+    ("f1", 59), ("f2", 60), ("f3", 61), ("f4", 62), ("f5", 63), ("f6", 64),
+    ("f7", 65), ("f8", 66), ("f9", 67), ("f10", 68), ("f11", 87), ("f12", 88),
+];
+
+/// Parses a key combination like `ctrl+shift+f1` into a key code and modifier bitfield.
+fn parse_hotkey(key: &str) -> Result<(i32, u8), String> {
+    let lower = key.to_lowercase();
+    let mut parts: Vec<_> = lower.split('+').collect();
+    let Some(name) = parts.pop() else { unreachable!("split always yields at least one part") };
+    let mut modifiers = 0;
+    for modifier in parts {
+        modifiers |= match modifier {
+            "ctrl" => 1,
+            "shift" => 2,
+            "alt" => 4,
+            _ => return Err(format!("Invalid modifier \"{modifier}\". Valid modifiers are ctrl, shift, and alt"))
+        };
+    }
+    let key_code = KEY_NAMES.iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, code)| *code)
+        .or_else(|| name.parse().ok())
+        .ok_or_else(|| format!("Invalid key \"{name}\". Keys can be letters, digits, F1 to F12, or LWJGL key codes"))?;
+    Ok((key_code, modifiers))
+}
+
+/// Formats a key code and modifier bitfield as a key combination like `ctrl+shift+f1`.
+fn hotkey_name(key_code: i32, modifiers: u8) -> String {
+    let mut name = String::new();
+    for (bit, modifier) in [(1, "ctrl+"), (2, "shift+"), (4, "alt+")] {
+        if modifiers & bit != 0 {
+            name.push_str(modifier);
+        }
+    }
+    match KEY_NAMES.iter().find(|(_, code)| *code == key_code) {
+        Some((key_name, _)) => name.push_str(key_name),
+        None => name.push_str(&key_code.to_string()),
+    }
+    name
+}
+
+/// Removes color codes like `&b` from a message.
+fn strip_colors(message: &str) -> String {
+    let mut stripped = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char == '&' && chars.peek().is_some_and(char::is_ascii_hexdigit) {
+            chars.next();
+        } else {
+            stripped.push(char);
+        }
+    }
+    stripped
+}
@@ -0,0 +1,89 @@
+//! Holds custom particle effects, and the effects that the server defines by default.
+
+use std::collections::BTreeMap;
+use crate::packets::Outgoing;
+
+/// The ID of the effect spawned when a block is broken.
+pub const BREAK_EFFECT: u8 = 0;
+/// The ID of the effect spawned when a player joins a world.
+pub const TELEPORT_EFFECT: u8 = 1;
+
+/// A custom particle effect, sent to clients that support `CustomParticles`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParticleEffect {
+    /// The minimum corner of the particle's texture in `particles.png`, in pixels.
+    pub texture_min: [u8; 2],
+    /// The maximum corner of the particle's texture in `particles.png`, in pixels, exclusive.
+    pub texture_max: [u8; 2],
+    /// The color the particle's texture is tinted with.
+    pub tint: [u8; 3],
+    /// The number of animation frames, laid out side by side in the texture.
+    pub frame_count: u8,
+    /// The number of particles spawned at once.
+    pub particle_count: u8,
+    /// The size of each particle, in 32nds of a block.
+    pub size: u8,
+    /// How much the size of each particle varies, in ten-thousandths of its size.
+    pub size_variation: i32,
+    /// How far away from the spawn position particles can appear, in 32nds of a block.
+    pub spread: u16,
+    /// How fast particles move away from their origin, in ten-thousandths of a block per second.
+    pub speed: i32,
+    /// How fast particles fall, in ten-thousandths of a block per second squared.
+    pub gravity: i32,
+    /// How long particles last, in ten-thousandths of a second.
+    pub lifetime: i32,
+    /// How much the lifetime of each particle varies, in ten-thousandths of its lifetime.
+    pub lifetime_variation: i32,
+    /// A bitfield of what particles collide with, laid out as in the `CustomParticles` specification.
+    pub collide_flags: u8,
+    /// Whether particles are always fully lit.
+    pub full_bright: bool,
+}
+
+impl ParticleEffect {
+    /// Gets the packet that defines this effect.
+    #[must_use]
+    pub fn packet(&self, id: u8) -> Outgoing {
+        Outgoing::DefineEffect { id, effect: self.clone() }
+    }
+}
+
+/// Creates the effects that the server defines by default.
+#[must_use]
+pub fn default_effects() -> BTreeMap<u8, ParticleEffect> {
+    BTreeMap::from([
+        (BREAK_EFFECT, ParticleEffect {
+            texture_min: [0, 0],
+            texture_max: [8, 8],
+            tint: [255; 3],
+            frame_count: 1,
+            particle_count: 8,
+            size: 4,
+            size_variation: 3000,
+            spread: 16,
+            speed: 15000,
+            gravity: 20000,
+            lifetime: 6000,
+            lifetime_variation: 2000,
+            collide_flags: 0,
+            full_bright: false,
+        }),
+        (TELEPORT_EFFECT, ParticleEffect {
+            texture_min: [0, 0],
+            texture_max: [8, 8],
+            tint: [160, 64, 255],
+            frame_count: 1,
+            particle_count: 24,
+            size: 3,
+            size_variation: 5000,
+            spread: 24,
+            speed: 5000,
+            gravity: -4000,
+            lifetime: 12000,
+            lifetime_variation: 4000,
+            collide_flags: 0,
+            full_bright: true,
+        }),
+    ])
+}
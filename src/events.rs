@@ -0,0 +1,123 @@
+//! Holds events that players can trigger, and the handlers that respond to them.
+
+use std::fmt;
+use mint::Vector3;
+use crate::{packets::Location, player::WeakPlayer, server::RunningServer};
+
+/// A mouse button that a player clicked with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    /// The left mouse button, used to break blocks.
+    Left,
+    /// The right mouse button, used to place blocks.
+    Right,
+    /// The middle mouse button, used to pick blocks.
+    Middle,
+}
+
+impl TryFrom<u8> for MouseButton {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(MouseButton::Left),
+            1 => Ok(MouseButton::Right),
+            2 => Ok(MouseButton::Middle),
+            _ => Err(value)
+        }
+    }
+}
+
+/// The face of a block that a player clicked on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockFace {
+    /// The face pointing away from the X origin.
+    PositiveX,
+    /// The face pointing towards the X origin.
+    NegativeX,
+    /// The top face.
+    PositiveY,
+    /// The bottom face.
+    NegativeY,
+    /// The face pointing away from the Z origin.
+    PositiveZ,
+    /// The face pointing towards the Z origin.
+    NegativeZ,
+}
+
+impl TryFrom<u8> for BlockFace {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(BlockFace::PositiveX),
+            1 => Ok(BlockFace::NegativeX),
+            2 => Ok(BlockFace::PositiveY),
+            3 => Ok(BlockFace::NegativeY),
+            4 => Ok(BlockFace::PositiveZ),
+            5 => Ok(BlockFace::NegativeZ),
+            _ => Err(value)
+        }
+    }
+}
+
+/// Sent when a player clicks, if their client supports `PlayerClick`.
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // Not every field is used by the built-in handlers
+pub struct ClickEvent {
+    /// The player who clicked.
+    pub player: WeakPlayer,
+    /// The button that was clicked.
+    pub button: MouseButton,
+    /// Whether the button was pressed, as opposed to released.
+    pub pressed: bool,
+    /// The player's yaw when they clicked.
+    pub yaw: i16,
+    /// The player's pitch when they clicked.
+    pub pitch: i16,
+    /// The ID of the player that was clicked on, if any.
+    pub target_entity: Option<i8>,
+    /// The position and face of the block that was clicked on, if any.
+    pub target_block: Option<(Vector3<u16>, BlockFace)>,
+}
+
+/// Responds to players clicking.
+pub trait ClickHandler: fmt::Debug + Send + Sync {
+    /// Handles a single click.
+    ///
+    /// This is called from the player's packet loop, so anything slow should be spawned as a task.
+    fn on_click(&self, event: &ClickEvent, server: &RunningServer);
+}
+
+/// Shows a player information about other players when they right click on them.
+#[derive(Debug)]
+pub struct PlayerInfo;
+
+impl ClickHandler for PlayerInfo {
+    fn on_click(&self, event: &ClickEvent, server: &RunningServer) {
+        let (MouseButton::Right, true, Some(id)) = (event.button, event.pressed, event.target_entity) else { return };
+        let player = event.player.clone();
+        let server = server.clone();
+        tokio::spawn(async move {
+            let Some(world) = player.world.upgrade() else { return };
+            let world = world.lock().clone();
+            let Some(target) = world.players.lock().get(&id).cloned() else { return };
+            let Some(name) = target.username.upgrade().and_then(|name| name.get().cloned()) else { return };
+            let world_name = world.data.lock().await.name.clone();
+            let rank = server.config.lock().rank_of(&name).display_name();
+
+            player.send_message(format!("&6[&e{name}&6]")).await;
+            player.send_message(format!("- World: {world_name}")).await;
+            player.send_message(format!("- Rank: {rank}")).await;
+            if let Some((_, average, _)) = target.latency() {
+                player.send_message(format!("- Ping: {}ms", average.as_millis())).await;
+            }
+            if let Some(location) = target.location.upgrade() {
+                let Vector3 { x, y, z } = Location::from(&*location).position;
+                player.send_message(format!(
+                    "- Position: {}, {}, {}", x.to_num::<i32>(), y.to_num::<i32>(), z.to_num::<i32>()
+                )).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,166 @@
+//! Holds the registry of supported CPE extensions, and the extensions negotiated with each client.
+//!
+//! Adding an extension only needs an entry in the `extensions!` invocation below.
+//! It's then advertised to clients in `ExtInfo`, negotiated from their `ExtEntry` packets,
+//! and its packets are only sent to and accepted from clients that negotiated it.
+
+use std::ops::RangeInclusive;
+
+/// How the server supports a single extension.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtensionInfo {
+    /// The name of the extension, as sent in `ExtEntry`.
+    pub name: &'static str,
+    /// The versions of the extension that the server supports. The highest is advertised to clients.
+    pub versions: RangeInclusive<u32>,
+    /// The IDs of the packets added by the extension, in either direction.
+    pub packets: &'static [u8],
+}
+
+macro_rules! extensions {
+    ($(
+        $(#[doc = $doc: literal])*
+        $variant: ident = $name: literal, versions $versions: expr, packets [$($packet: literal),*];
+    )+) => {
+        /// A CPE extension that the server supports.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum Extension {
+            $($(#[doc = $doc])* $variant,)+
+        }
+
+        impl Extension {
+            /// Every extension that the server supports, in the order they're advertised.
+            pub const ALL: &'static [Extension] = &[$(Extension::$variant),+];
+
+            /// Gets how the server supports this extension.
+            #[must_use]
+            pub fn info(self) -> ExtensionInfo {
+                match self {
+                    $(Extension::$variant => ExtensionInfo {
+                        name: $name,
+                        versions: $versions,
+                        packets: &[$($packet),*],
+                    },)+
+                }
+            }
+        }
+    };
+}
+
+extensions! {
+    /// Lets chat use every CP437 character.
+    FullCp437 = "FullCP437", versions 1..=1, packets [];
+    /// Lets chat messages span multiple packets.
+    LongerMessages = "LongerMessages", versions 1..=1, packets [];
+    /// Stops emotes at the end of messages from being cut off.
+    EmoteFix = "EmoteFix", versions 1..=1, packets [];
+    /// Adds blocks 50 to 65.
+    CustomBlocks = "CustomBlocks", versions 1..=1, packets [0x13];
+    /// Lets the server define new blocks.
+    BlockDefinitions = "BlockDefinitions", versions 1..=1, packets [0x23, 0x24];
+    /// Lets block definitions have custom bounding boxes and textures on each side.
+    BlockDefinitionsExt = "BlockDefinitionsExt", versions 2..=2, packets [0x25];
+    /// Lets the server control the player list and the names of entities.
+    ExtPlayerList = "ExtPlayerList", versions 2..=2, packets [0x16, 0x18, 0x21];
+    /// Lets the server see and change the block that players are holding.
+    HeldBlock = "HeldBlock", versions 1..=1, packets [0x14];
+    /// Lets the server control which blocks players can place and delete.
+    BlockPermissions = "BlockPermissions", versions 1..=1, packets [0x1c];
+    /// Lets the server change players' hotbars.
+    SetHotbar = "SetHotbar", versions 1..=1, packets [0x2d];
+    /// Lets the server change the colors of the environment.
+    EnvColors = "EnvColors", versions 1..=1, packets [0x19];
+    /// Lets the server change the side and edge blocks and the water level.
+    EnvMapAspect = "EnvMapAspect", versions 1..=1, packets [0x29];
+    /// Lets the server change the weather.
+    EnvWeatherType = "EnvWeatherType", versions 1..=1, packets [0x1f];
+    /// Lets messages be shown in places other than chat.
+    MessageTypes = "MessageTypes", versions 1..=1, packets [];
+    /// Lets both sides measure latency.
+    TwoWayPing = "TwoWayPing", versions 1..=1, packets [0x2b];
+    /// Sends entity positions with 32-bit coordinates.
+    ExtEntityPositions = "ExtEntityPositions", versions 1..=1, packets [];
+    /// Sends levels as raw DEFLATE streams.
+    FastMap = "FastMap", versions 1..=1, packets [];
+    /// Lets clients tell the server when they click.
+    PlayerClick = "PlayerClick", versions 1..=1, packets [0x22];
+    /// Lets the server highlight regions of the level.
+    SelectionCuboid = "SelectionCuboid", versions 1..=1, packets [0x1a, 0x1b];
+    /// Lets the server change how entities look.
+    ChangeModel = "ChangeModel", versions 1..=1, packets [0x1d];
+    /// Lets the server change the rotation and scale of entities.
+    EntityProperty = "EntityProperty", versions 1..=1, packets [0x2a];
+    /// Lets the server control which hacks players can use.
+    HackControl = "HackControl", versions 1..=1, packets [0x20];
+    /// Lets the server change how far players can reach.
+    ClickDistance = "ClickDistance", versions 1..=1, packets [0x12];
+    /// Lets the server bind keys to send messages.
+    TextHotKey = "TextHotKey", versions 1..=1, packets [0x15];
+    /// Lets the server order and hide blocks in players' inventories.
+    InventoryOrder = "InventoryOrder", versions 1..=1, packets [0x2c];
+    /// Lets the server define and spawn particle effects.
+    CustomParticles = "CustomParticles", versions 1..=1, packets [0x30, 0x31];
+    /// Lets the server change where players respawn.
+    SetSpawnpoint = "SetSpawnpoint", versions 1..=1, packets [0x2e];
+    /// Lets the server change players' velocity.
+    VelocityControl = "VelocityControl", versions 1..=1, packets [0x2f];
+    /// Lets the server change many blocks in a single packet.
+    BulkBlockUpdate = "BulkBlockUpdate", versions 1..=1, packets [0x26];
+}
+
+impl Extension {
+    /// Finds an extension by the name sent in `ExtEntry`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Extension> {
+        Extension::ALL.iter().copied().find(|extension| extension.info().name == name)
+    }
+
+    /// Finds the extension that added a packet, or `None` if the packet is part of the base protocol or CPE itself.
+    #[must_use]
+    pub fn for_packet(id: u8) -> Option<Extension> {
+        Extension::ALL.iter().copied().find(|extension| extension.info().packets.contains(&id))
+    }
+}
+
+/// The extensions negotiated with a client, and the version of each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SupportedExtensions {
+    /// The negotiated version of each extension, indexed by [`Extension`]. 0 means the extension isn't supported.
+    versions: [u32; Extension::ALL.len()],
+}
+
+impl SupportedExtensions {
+    /// Creates a set with no extensions, as used by clients that don't support CPE.
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self { versions: [0; Extension::ALL.len()] }
+    }
+
+    /// Checks if an extension was negotiated.
+    #[must_use]
+    pub fn contains(&self, extension: Extension) -> bool {
+        self.versions[extension as usize] != 0
+    }
+
+    /// Gets the negotiated version of an extension, or `None` if it wasn't negotiated.
+    #[must_use]
+    pub fn version(&self, extension: Extension) -> Option<u32> {
+        Some(self.versions[extension as usize]).filter(|&version| version != 0)
+    }
+
+    /// Negotiates an extension from a client's `ExtEntry`, returning the version that will be used.
+    ///
+    /// Each side is assumed to support every version up to the one it advertises,
+    /// so the highest version supported by both is used.
+    /// Returns `None` if the server doesn't support the extension, or no version is supported by both.
+    pub fn negotiate(&mut self, name: &str, their_version: u32) -> Option<u32> {
+        let extension = Extension::from_name(name)?;
+        let versions = extension.info().versions;
+        let version = their_version.min(*versions.end());
+        if !versions.contains(&version) {
+            return None;
+        }
+        self.versions[extension as usize] = version;
+        Some(version)
+    }
+}
@@ -1,7 +1,7 @@
 //! Handles the reading and writing of a level.
 #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 
-use std::{io::{self, Cursor, ErrorKind, Read, Seek, Write}, iter};
+use std::{collections::BTreeSet, io::{self, Cursor, ErrorKind, Read, Seek, Write}, iter};
 
 use arrayvec::ArrayVec;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
@@ -10,8 +10,9 @@ use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 use jaded::Parser;
 use mint::Vector3;
 
-use crate::packets::{Location, x16};
-use crate::world::{LevelData, WorldData};
+use crate::blocks::{self, BlockDefinition};
+use crate::packets::{Location, x32};
+use crate::world::{Environment, HackPolicy, HotKey, LevelData, RankRequirement, WorldData, WorldPermissions};
 
 /// An instance of Java world data.
 #[derive(jaded::FromJava)]
@@ -27,6 +28,13 @@ struct JavaWorld {
     zSpawn: i32,
     rotSpawn: f32
 }
+/// Reads a fixed-size array of bytes.
+fn read_array<const SIZE: usize>(mut stream: impl Read) -> io::Result<[u8; SIZE]> {
+    let mut buf = [0; SIZE];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
 macro_rules! invalid {
     ($($f: tt)+) => {
         io::Error::new(ErrorKind::InvalidData, format!($($f)+))
@@ -34,7 +42,61 @@ macro_rules! invalid {
 }
 
 const MAGIC: &[u8] = b"HONEYLV";
-const VERSION: u8 = 0;
+const VERSION: u8 = 3;
+
+/// The tag of the section holding a world's custom block definitions.
+const BLOCK_DEFINITIONS_TAG: [u8; 4] = *b"BDEF";
+/// The tag of the section holding a world's environment settings.
+const ENVIRONMENT_TAG: [u8; 4] = *b"ENVI";
+/// The tag of the section holding a world's hack policy.
+const HACKS_TAG: [u8; 4] = *b"HACK";
+/// The tag of the section holding a world's reach distance.
+const REACH_TAG: [u8; 4] = *b"RECH";
+/// The tag of the section holding a world's hotkeys.
+const HOTKEYS_TAG: [u8; 4] = *b"HKEY";
+/// The tag of the section holding a world's inventory palette.
+const PALETTE_TAG: [u8; 4] = *b"PALT";
+/// The tag of the section holding who can place and delete blocks in a world.
+const BLOCK_ACCESS_TAG: [u8; 4] = *b"BACC";
+/// The tag of the section holding a world's owner, and who can visit and build in it.
+const PERMISSIONS_TAG: [u8; 4] = *b"PERM";
+
+/// Reads a length-prefixed CP437 string of at most 64 bytes.
+fn read_string(mut stream: impl Read) -> io::Result<String> {
+    let len = stream.read_u8()?;
+    if len > 64 {
+        return Err(invalid!("string must not be larger than 64 bytes"));
+    }
+    let mut raw: ArrayVec<u8, 64> = iter::repeat(0).take(len as usize).collect();
+    stream.read_exact(&mut raw)?;
+    Ok(String::borrow_from_cp437(raw.as_ref(), &CP437_WINGDINGS))
+}
+
+/// Reads a rank requirement, which was a single byte before version 3.
+fn read_rank_requirement(mut stream: impl Read, version: u8) -> io::Result<RankRequirement> {
+    if version < 3 {
+        let value = stream.read_u8()?;
+        RankRequirement::from_legacy(value).ok_or_else(|| invalid!("Invalid rank requirement {value}"))
+    } else {
+        RankRequirement::try_from(read_array(stream)?).map_err(|kind| invalid!("Invalid rank requirement kind {kind}"))
+    }
+}
+
+/// Writes a rank requirement.
+fn write_rank_requirement(mut stream: impl Write, requirement: RankRequirement) -> io::Result<()> {
+    stream.write_all(&<[u8; 2]>::from(requirement))
+}
+
+/// Writes a length-prefixed CP437 string of at most 64 bytes.
+fn write_string(mut stream: impl Write, string: &str) -> io::Result<()> {
+    let cp437 = string.to_cp437(&CP437_WINGDINGS)
+        .map_err(|err| invalid!("string is invalid CP437 (valid up to character {})", err.representable_up_to))?;
+    if cp437.len() > 64 {
+        return Err(invalid!("string must not be larger than 64 bytes"));
+    }
+    stream.write_u8(cp437.len() as u8)?;
+    stream.write_all(&cp437)
+}
 
 impl WorldData {
     /// Load world data from any supported file.
@@ -82,7 +144,7 @@ impl WorldData {
 
         let spawn_point = Location {
             position: Vector3 {
-                x: x16::from_num(object.xSpawn), y: x16::from_num(object.ySpawn), z: x16::from_num(object.zSpawn)
+                x: x32::from_num(object.xSpawn), y: x32::from_num(object.ySpawn), z: x32::from_num(object.zSpawn)
             },
             pitch: 0,
             yaw: (object.rotSpawn / 360.0 * 256.0) as u8
@@ -97,6 +159,7 @@ impl WorldData {
             level_data,
             spawn_point,
             name: object.name,
+            ..WorldData::default()
         })
     }
 
@@ -106,12 +169,74 @@ impl WorldData {
     /// - Magic: `b"HONEYLV"`
     /// - File version: `u8`
     /// - World dimensions: `[u16; 3]`
-    /// - Spawn position: `[u16; 3]` (fixed point, 5 bits after the decimal)
+    /// - Spawn position: `[i32; 3]` (fixed point, 5 bits after the decimal)
+    ///   - Before version 2, this is `[u16; 3]`
     /// - Spawn rotation: `[u8; 2]`
     /// - Level name length: `u8` (less than 64)
     /// - Level name: `[u8]` (CP437-encoded string)
+    /// - Section count: `u16` (since version 1)
+    /// - Sections (since version 1), each made of:
+    ///   - Tag: `[u8; 4]`
+    ///   - Section length: `u32`
+    ///   - Section data: `[u8]`
     /// - Unzipped level data size: `u64`
     /// - Gzipped level data: `[u8]`
+    ///
+    /// Sections with unknown tags are skipped. The known sections are:
+    /// - `b"BDEF"`: Custom block definitions
+    ///   - Definition count: `u16`
+    ///   - Definitions, each made of:
+    ///     - Block ID: `u8`
+    ///     - Name length: `u8` (less than 64)
+    ///     - Name: `[u8]` (CP437-encoded string)
+    ///     - Solidity, speed: `[u8; 2]`
+    ///     - Textures: `[u8; 6]` (top, left, right, front, back, bottom)
+    ///     - Transmits light, walk sound, full bright, sprite: `[u8; 4]`
+    ///     - Bounding box: `[u8; 6]` (minimum corner, then maximum corner)
+    ///     - Draw mode, fog density: `[u8; 2]`
+    ///     - Fog color: `[u8; 3]`
+    ///     - Fallback block: `u8`
+    /// - `b"ENVI"`: Environment settings
+    ///   - Colors, each made of:
+    ///     - Whether the color is set: `u8`
+    ///     - Color: `[u8; 3]`
+    ///   - Whether the side block is set, and the side block: `[u8; 2]`
+    ///   - Whether the edge block is set, and the edge block: `[u8; 2]`
+    ///   - Whether the water level is set: `u8`
+    ///   - Water level: `i32`
+    ///   - Weather: `u8`
+    /// - `b"HACK"`: Hack policy
+    ///   - Allowed hacks: `u8` (a bitfield of flying, noclip, speeding, respawn, third person,
+    ///     and operator bypass, from the lowest bit)
+    ///   - Whether the jump height is set: `u8`
+    ///   - Jump height: `i16`
+    /// - `b"RECH"`: Reach distance
+    ///   - Reach: `i16` (in 32nds of a block)
+    /// - `b"HKEY"`: Hotkeys
+    ///   - Hotkey count: `u16`
+    ///   - Hotkeys, each made of:
+    ///     - Action length: `u8` (less than 64)
+    ///     - Action: `[u8]` (CP437-encoded string)
+    ///     - Key code: `i32`
+    ///     - Modifiers: `u8`
+    /// - `b"PALT"`: Inventory palette
+    ///   - Block IDs: `[u8]` (the rest of the section, in inventory order)
+    /// - `b"BACC"`: Block access
+    ///   - Overrides, each made of (for the rest of the section):
+    ///     - Block ID: `u8`
+    ///     - Access: `[u8; 2]` (0 and the lowest rank level that can use the block, or 1 and 0 for nobody)
+    ///       - Before version 3, this is `u8` (0 for everyone, 1 for operators, and 2 for nobody)
+    /// - `b"PERM"`: Permissions
+    ///   - Whether the owner is set: `u8`
+    ///   - Owner length: `u8` (less than 64)
+    ///   - Owner: `[u8]` (CP437-encoded string)
+    ///   - Builder count: `u16`
+    ///   - Builders, each made of:
+    ///     - Username length: `u8` (less than 64)
+    ///     - Username: `[u8]` (CP437-encoded string)
+    ///   - Minimum rank to visit: `[u8; 2]` (0 and the lowest rank level that can visit, or 1 and 0 for nobody)
+    ///     - Before version 3, this is `u8` (0 for guest, 1 for operator, and 2 for nobody)
+    ///   - Minimum rank to build: `[u8; 2]` (as above)
     /// 
     /// All values are in big endian.
     /// 
@@ -128,8 +253,8 @@ impl WorldData {
         // Check file version
         let version = stream.read_u8()
             .map_err(|err| invalid!("Failed to read file version: {err}"))?;
-        if version != VERSION {
-            return Err(invalid!("Incorrect file version {version} (expected {VERSION})"));
+        if version > VERSION {
+            return Err(invalid!("Unsupported file version {version} (expected {VERSION} or below)"));
         }
         // Get dimensions, player spawn, and level name
         // NOTE: Since packets use AsyncRead and AsyncWrite, we can't use their implementations
@@ -138,10 +263,15 @@ impl WorldData {
             .map_err(|err| invalid!("Failed to read level dimensions: {err}"))?;
         let dimensions = Vector3::<u16>::from(dimensions);
 
-        let mut spawn_position = [0u16; 3];
-        stream.read_u16_into::<BigEndian>(&mut spawn_position)
-            .map_err(|err| invalid!("Failed to read player spawn position: {err}"))?;
-        let position = Vector3::<x16>::from(spawn_position.map(x16::from_bits));
+        let mut spawn_position = [0i32; 3];
+        if version >= 2 {
+            stream.read_i32_into::<BigEndian>(&mut spawn_position)
+        } else {
+            let mut old_position = [0u16; 3];
+            stream.read_u16_into::<BigEndian>(&mut old_position)
+                .map(|()| spawn_position = old_position.map(i32::from))
+        }.map_err(|err| invalid!("Failed to read player spawn position: {err}"))?;
+        let position = Vector3::<x32>::from(spawn_position.map(x32::from_bits));
 
         let mut yaw_pitch = [0u8; 2];
         stream.read_exact(&mut yaw_pitch)
@@ -149,14 +279,18 @@ impl WorldData {
         let [yaw, pitch] = yaw_pitch;
         
         // Get level name
-        let name_len = stream.read_u8()?;
-        if name_len > 64 {
-            return Err(invalid!("Failed to read level name: name must not be larger than 64 bytes"));
-        }
-        let mut raw_level_name: ArrayVec<u8, 64> = iter::repeat(0).take(name_len as usize).collect();
-        stream.read_exact(&mut raw_level_name)
+        let level_name = read_string(&mut stream)
             .map_err(|err| invalid!("Failed to read level name: {err}"))?;
-        let level_name = String::borrow_from_cp437(raw_level_name.as_ref(), &CP437_WINGDINGS);
+
+        let mut world = WorldData {
+            spawn_point: Location { position, yaw, pitch },
+            name: level_name,
+            ..WorldData::default()
+        };
+
+        if version >= 1 {
+            world.load_sections(&mut stream, version)?;
+        }
 
         // Get unzipped data length
         let raw_length = stream.read_u64::<BigEndian>()
@@ -171,11 +305,299 @@ impl WorldData {
         decoder.read_to_end(&mut raw_data)
             .map_err(|err| invalid!("Failed to decode level data: {err}"))?;
 
-        Ok( WorldData {
-            level_data: LevelData { raw_data, dimensions },
-            spawn_point: Location { position, yaw, pitch },
-            name: level_name
-        } )
+        world.level_data = LevelData { raw_data, dimensions };
+        Ok(world)
+    }
+
+    /// Loads the sections of a .hbit file, given its file version.
+    fn load_sections(&mut self, mut stream: impl Read, version: u8) -> io::Result<()> {
+        let count = stream.read_u16::<BigEndian>()
+            .map_err(|err| invalid!("Failed to read section count: {err}"))?;
+        for _ in 0..count {
+            let mut tag = [0; 4];
+            stream.read_exact(&mut tag)
+                .map_err(|err| invalid!("Failed to read section tag: {err}"))?;
+            let tag_name = String::from_utf8_lossy(&tag).into_owned();
+            let length = stream.read_u32::<BigEndian>()
+                .map_err(|err| invalid!("Failed to read length of section {tag_name}: {err}"))?;
+            let mut section = Vec::new();
+            (&mut stream).take(u64::from(length)).read_to_end(&mut section)
+                .map_err(|err| invalid!("Failed to read section {tag_name}: {err}"))?;
+            if section.len() != length as usize {
+                return Err(invalid!("Failed to read section {tag_name}: unexpected end of file"));
+            }
+            let mut section = Cursor::new(section);
+            match tag {
+                BLOCK_DEFINITIONS_TAG => self.load_block_definitions(&mut section),
+                ENVIRONMENT_TAG => self.load_environment(&mut section),
+                HACKS_TAG => self.load_hacks(&mut section),
+                REACH_TAG => section.read_i16::<BigEndian>().map(|reach| self.reach = Some(reach)),
+                HOTKEYS_TAG => self.load_hotkeys(&mut section),
+                PALETTE_TAG => section.read_to_end(&mut self.palette)
+                    .map(|_| blocks::clean_palette(&mut self.palette)),
+                BLOCK_ACCESS_TAG => self.load_block_access(&mut section, version),
+                PERMISSIONS_TAG => self.load_permissions(&mut section, version),
+                _ => {
+                    warn!("Skipping unknown section {tag_name}");
+                    Ok(())
+                }
+            }.map_err(|err| invalid!("Failed to decode section {tag_name}: {err}"))?;
+        }
+        Ok(())
+    }
+
+    /// Loads the block definitions section of a .hbit file.
+    fn load_block_definitions(&mut self, mut stream: impl Read) -> io::Result<()> {
+        let count = stream.read_u16::<BigEndian>()?;
+        for _ in 0..count {
+            let id = stream.read_u8()?;
+            let name = read_string(&mut stream)?;
+            let [solidity, speed] = read_array(&mut stream)?;
+            let textures = read_array(&mut stream)?;
+            let [transmits_light, walk_sound, full_bright, sprite] = read_array(&mut stream)?;
+            let [min_x, min_y, min_z, max_x, max_y, max_z] = read_array(&mut stream)?;
+            let [draw, fog_density] = read_array(&mut stream)?;
+            let fog_color = read_array(&mut stream)?;
+            let fallback = stream.read_u8()?;
+            self.block_definitions.insert(id, BlockDefinition {
+                name,
+                solidity,
+                speed,
+                textures,
+                transmits_light: transmits_light != 0,
+                walk_sound,
+                full_bright: full_bright != 0,
+                sprite: sprite != 0,
+                min: Vector3 { x: min_x, y: min_y, z: min_z },
+                max: Vector3 { x: max_x, y: max_y, z: max_z },
+                draw,
+                fog_density,
+                fog_color,
+                fallback,
+            });
+        }
+        Ok(())
+    }
+
+    /// Stores the block definitions section of a .hbit file.
+    fn store_block_definitions(&self, mut stream: impl Write) -> io::Result<()> {
+        stream.write_u16::<BigEndian>(self.block_definitions.len() as u16)?;
+        for (id, definition) in &self.block_definitions {
+            stream.write_u8(*id)?;
+            write_string(&mut stream, &definition.name)?;
+            stream.write_all(&[definition.solidity, definition.speed])?;
+            stream.write_all(&definition.textures)?;
+            stream.write_all(&[
+                u8::from(definition.transmits_light),
+                definition.walk_sound,
+                u8::from(definition.full_bright),
+                u8::from(definition.sprite)
+            ])?;
+            stream.write_all(&[
+                definition.min.x, definition.min.y, definition.min.z,
+                definition.max.x, definition.max.y, definition.max.z
+            ])?;
+            stream.write_all(&[definition.draw, definition.fog_density])?;
+            stream.write_all(&definition.fog_color)?;
+            stream.write_u8(definition.fallback)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the environment section of a .hbit file.
+    fn load_environment(&mut self, mut stream: impl Read) -> io::Result<()> {
+        let environment = &mut self.environment;
+        for color in &mut environment.colors {
+            let [set, r, g, b] = read_array(&mut stream)?;
+            *color = (set != 0).then_some([r, g, b]);
+        }
+        let [set, side_block] = read_array(&mut stream)?;
+        environment.side_block = (set != 0).then_some(side_block);
+        let [set, edge_block] = read_array(&mut stream)?;
+        environment.edge_block = (set != 0).then_some(edge_block);
+        let set = stream.read_u8()?;
+        let water_level = stream.read_i32::<BigEndian>()?;
+        environment.water_level = (set != 0).then_some(water_level);
+        environment.weather = stream.read_u8()?;
+        Ok(())
+    }
+
+    /// Stores the environment section of a .hbit file.
+    fn store_environment(&self, mut stream: impl Write) -> io::Result<()> {
+        let environment = &self.environment;
+        for color in environment.colors {
+            let [r, g, b] = color.unwrap_or_default();
+            stream.write_all(&[u8::from(color.is_some()), r, g, b])?;
+        }
+        stream.write_all(&[u8::from(environment.side_block.is_some()), environment.side_block.unwrap_or_default()])?;
+        stream.write_all(&[u8::from(environment.edge_block.is_some()), environment.edge_block.unwrap_or_default()])?;
+        stream.write_u8(u8::from(environment.water_level.is_some()))?;
+        stream.write_i32::<BigEndian>(environment.water_level.unwrap_or_default())?;
+        stream.write_u8(environment.weather)
+    }
+
+    /// Loads the hack policy section of a .hbit file.
+    fn load_hacks(&mut self, mut stream: impl Read) -> io::Result<()> {
+        let [allowed, set] = read_array(&mut stream)?;
+        let jump_height = stream.read_i16::<BigEndian>()?;
+        let [flying, noclip, speeding, respawn, third_person, operator_bypass] =
+            std::array::from_fn(|bit| allowed & (1 << bit) != 0);
+        self.hacks = HackPolicy {
+            flying,
+            noclip,
+            speeding,
+            respawn,
+            third_person,
+            jump_height: (set != 0).then_some(jump_height),
+            operator_bypass,
+        };
+        Ok(())
+    }
+
+    /// Stores the hack policy section of a .hbit file.
+    fn store_hacks(&self, mut stream: impl Write) -> io::Result<()> {
+        let hacks = self.hacks;
+        let allowed = [hacks.flying, hacks.noclip, hacks.speeding, hacks.respawn, hacks.third_person, hacks.operator_bypass]
+            .into_iter()
+            .enumerate()
+            .fold(0u8, |bits, (bit, allowed)| bits | u8::from(allowed) << bit);
+        stream.write_all(&[allowed, u8::from(hacks.jump_height.is_some())])?;
+        stream.write_i16::<BigEndian>(hacks.jump_height.unwrap_or_default())
+    }
+
+    /// Loads the hotkeys section of a .hbit file.
+    fn load_hotkeys(&mut self, mut stream: impl Read) -> io::Result<()> {
+        let count = stream.read_u16::<BigEndian>()?;
+        for _ in 0..count {
+            let action = read_string(&mut stream)?;
+            let key_code = stream.read_i32::<BigEndian>()?;
+            let modifiers = stream.read_u8()?;
+            self.hotkeys.push(HotKey { action, key_code, modifiers });
+        }
+        Ok(())
+    }
+
+    /// Stores the hotkeys section of a .hbit file.
+    fn store_hotkeys(&self, mut stream: impl Write) -> io::Result<()> {
+        stream.write_u16::<BigEndian>(self.hotkeys.len() as u16)?;
+        for hotkey in &self.hotkeys {
+            write_string(&mut stream, &hotkey.action)?;
+            stream.write_i32::<BigEndian>(hotkey.key_code)?;
+            stream.write_u8(hotkey.modifiers)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the block access section of a .hbit file.
+    fn load_block_access(&mut self, mut stream: impl Read, version: u8) -> io::Result<()> {
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw)?;
+        let mut raw = raw.as_slice();
+        while let Ok(id) = raw.read_u8() {
+            let access = read_rank_requirement(&mut raw, version)
+                .map_err(|err| invalid!("Invalid access for block {id}: {err}"))?;
+            self.block_access.insert(id, access);
+        }
+        Ok(())
+    }
+
+    /// Loads the permissions section of a .hbit file.
+    fn load_permissions(&mut self, mut stream: impl Read, version: u8) -> io::Result<()> {
+        let has_owner = stream.read_u8()?;
+        let owner = read_string(&mut stream)?;
+        let count = stream.read_u16::<BigEndian>()?;
+        let mut builders = BTreeSet::new();
+        for _ in 0..count {
+            builders.insert(read_string(&mut stream)?);
+        }
+        self.permissions = WorldPermissions {
+            owner: (has_owner != 0).then_some(owner),
+            builders,
+            visit: read_rank_requirement(&mut stream, version)?,
+            build: read_rank_requirement(&mut stream, version)?,
+        };
+        Ok(())
+    }
+
+    /// Stores the permissions section of a .hbit file.
+    fn store_permissions(&self, mut stream: impl Write) -> io::Result<()> {
+        let permissions = &self.permissions;
+        stream.write_u8(u8::from(permissions.owner.is_some()))?;
+        write_string(&mut stream, permissions.owner.as_deref().unwrap_or_default())?;
+        stream.write_u16::<BigEndian>(permissions.builders.len() as u16)?;
+        for builder in &permissions.builders {
+            write_string(&mut stream, builder)?;
+        }
+        write_rank_requirement(&mut stream, permissions.visit)?;
+        write_rank_requirement(&mut stream, permissions.build)
+    }
+
+    /// Stores the sections of a .hbit file.
+    fn store_sections(&self, mut stream: impl Write) -> io::Result<()> {
+        let mut sections: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+
+        if !self.block_definitions.is_empty() {
+            let mut section = Vec::new();
+            self.store_block_definitions(&mut section)
+                .map_err(|err| invalid!("Failed to encode block definitions: {err}"))?;
+            sections.push((BLOCK_DEFINITIONS_TAG, section));
+        }
+
+        if self.environment != Environment::default() {
+            let mut section = Vec::new();
+            self.store_environment(&mut section)
+                .map_err(|err| invalid!("Failed to encode environment: {err}"))?;
+            sections.push((ENVIRONMENT_TAG, section));
+        }
+
+        if self.hacks != HackPolicy::default() {
+            let mut section = Vec::new();
+            self.store_hacks(&mut section)
+                .map_err(|err| invalid!("Failed to encode hack policy: {err}"))?;
+            sections.push((HACKS_TAG, section));
+        }
+
+        if let Some(reach) = self.reach {
+            sections.push((REACH_TAG, reach.to_be_bytes().to_vec()));
+        }
+
+        if !self.hotkeys.is_empty() {
+            let mut section = Vec::new();
+            self.store_hotkeys(&mut section)
+                .map_err(|err| invalid!("Failed to encode hotkeys: {err}"))?;
+            sections.push((HOTKEYS_TAG, section));
+        }
+
+        if !self.palette.is_empty() {
+            sections.push((PALETTE_TAG, self.palette.clone()));
+        }
+
+        if !self.block_access.is_empty() {
+            let mut section = Vec::new();
+            for (&id, &access) in &self.block_access {
+                section.push(id);
+                write_rank_requirement(&mut section, access)
+                    .map_err(|err| invalid!("Failed to encode block access: {err}"))?;
+            }
+            sections.push((BLOCK_ACCESS_TAG, section));
+        }
+
+        if self.permissions != WorldPermissions::default() {
+            let mut section = Vec::new();
+            self.store_permissions(&mut section)
+                .map_err(|err| invalid!("Failed to encode permissions: {err}"))?;
+            sections.push((PERMISSIONS_TAG, section));
+        }
+
+        stream.write_u16::<BigEndian>(sections.len() as u16)
+            .map_err(|err| invalid!("Failed to write section count: {err}"))?;
+        for (tag, section) in sections {
+            stream.write_all(&tag)
+                .and_then(|()| stream.write_u32::<BigEndian>(section.len() as u32))
+                .and_then(|()| stream.write_all(&section))
+                .map_err(|err| invalid!("Failed to write section {}: {err}", String::from_utf8_lossy(&tag)))?;
+        }
+        Ok(())
     }
 
     /// Store the world data into a .hbit file.
@@ -193,23 +615,18 @@ impl WorldData {
             .and_then(|()| stream.write_u16::<BigEndian>(self.level_data.dimensions.y))
             .and_then(|()| stream.write_u16::<BigEndian>(self.level_data.dimensions.z))
             .map_err(|err| invalid!("Failed to write level dimensions: {err}"))?;
-        stream.write_u16::<BigEndian>(self.spawn_point.position.x.to_bits())
-            .and_then(|()| stream.write_u16::<BigEndian>(self.spawn_point.position.y.to_bits()))
-            .and_then(|()| stream.write_u16::<BigEndian>(self.spawn_point.position.z.to_bits()))
+        stream.write_i32::<BigEndian>(self.spawn_point.position.x.to_bits())
+            .and_then(|()| stream.write_i32::<BigEndian>(self.spawn_point.position.y.to_bits()))
+            .and_then(|()| stream.write_i32::<BigEndian>(self.spawn_point.position.z.to_bits()))
             .map_err(|err| invalid!("Failed to write player spawn position: {err}"))?;
         stream.write_u8(self.spawn_point.yaw)
-            .and_then(|()| stream.write_u8(self.spawn_point.yaw))
+            .and_then(|()| stream.write_u8(self.spawn_point.pitch))
             .map_err(|err| invalid!("Failed to write player spawn rotation: {err}"))?;
         // Write the level name
-        let cp437_name = self.name.to_cp437(&CP437_WINGDINGS)
-            .map_err(|err| invalid!("Failed to write level name: string is invalid CP437 (valid up to character {})", err.representable_up_to))?;
-        if cp437_name.len() > 64 {
-            return Err(invalid!("Failed to write level name: name must not be larger than 64 bytes"));
-        }
-        stream.write_u8(cp437_name.len() as u8)
-            .map_err(|err| invalid!("Failed to write level name: {err}"))?;
-        stream.write_all(&cp437_name)
+        write_string(&mut stream, &self.name)
             .map_err(|err| invalid!("Failed to write level name: {err}"))?;
+        // Write the extra sections
+        self.store_sections(&mut stream)?;
         // Write the level data
         stream.write_u64::<BigEndian>(self.level_data.raw_data.len() as u64)
             .map_err(|err| invalid!("Failed to write level data length: {err}"))?;
@@ -218,3 +635,129 @@ impl WorldData {
             .map_err(|err| invalid!("Failed to encode level data: {err}"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ranks;
+
+    /// Builds a world with every section set to something other than its default.
+    fn full_world() -> WorldData {
+        let mut world = WorldData {
+            level_data: LevelData {
+                raw_data: (0..24).collect(),
+                dimensions: Vector3 { x: 4, y: 3, z: 2 },
+            },
+            spawn_point: Location {
+                position: Vector3 { x: x32::from_num(1.5), y: x32::from_num(2), z: x32::from_num(-3.25) },
+                yaw: 64,
+                pitch: 32,
+            },
+            name: "Round trip".into(),
+            reach: Some(96),
+            palette: vec![1, 4, 66],
+            ..WorldData::default()
+        };
+        world.block_definitions.insert(66, BlockDefinition::new("Custom".into(), 12, 1));
+        world.environment.colors[0] = Some([1, 2, 3]);
+        world.environment.side_block = Some(4);
+        world.environment.water_level = Some(-8);
+        world.environment.weather = 2;
+        world.hacks.flying = false;
+        world.hacks.jump_height = Some(40);
+        world.hotkeys.push(HotKey { action: "/spawn◙".into(), key_code: 35, modifiers: 1 });
+        world.block_access.insert(7, RankRequirement::Level(0));
+        world.block_access.insert(20, RankRequirement::Level(75));
+        world.block_access.insert(46, RankRequirement::Nobody);
+        world.permissions.owner = Some("owner".into());
+        world.permissions.builders.insert("builder".into());
+        world.permissions.visit = RankRequirement::Level(10);
+        world.permissions.build = RankRequirement::Nobody;
+        world
+    }
+
+    #[test]
+    fn store_then_load_keeps_every_section() {
+        let world = full_world();
+        let mut buf = Vec::new();
+        world.store(&mut buf).unwrap();
+        let loaded = WorldData::load(buf.as_slice()).unwrap();
+
+        assert_eq!(loaded.level_data, world.level_data);
+        assert_eq!(loaded.spawn_point, world.spawn_point);
+        assert_eq!(loaded.name, world.name);
+        assert_eq!(loaded.block_definitions, world.block_definitions);
+        assert_eq!(loaded.environment, world.environment);
+        assert_eq!(loaded.hacks, world.hacks);
+        assert_eq!(loaded.reach, world.reach);
+        assert_eq!(loaded.hotkeys, world.hotkeys);
+        assert_eq!(loaded.palette, world.palette);
+        assert_eq!(loaded.block_access, world.block_access);
+        assert_eq!(loaded.permissions, world.permissions);
+    }
+
+    #[test]
+    fn loaded_palettes_are_cleaned() {
+        let mut world = full_world();
+        world.palette = (0..=u8::MAX).chain([4, 1, 4]).collect();
+        let mut buf = Vec::new();
+        world.store(&mut buf).unwrap();
+        let loaded = WorldData::load(buf.as_slice()).unwrap();
+
+        assert_eq!(loaded.palette, (1..=u8::MAX).collect::<Vec<_>>());
+        let order = blocks::inventory_order(&loaded.palette);
+        assert!(matches!(order.last(), Some(crate::packets::Outgoing::SetInventoryOrder { block: 255, order: 255 })));
+    }
+
+    /// Writes a section with its tag and length.
+    fn section(stream: &mut Vec<u8>, tag: [u8; 4], section: &[u8]) {
+        stream.extend(tag);
+        stream.write_u32::<BigEndian>(section.len() as u32).unwrap();
+        stream.extend(section);
+    }
+
+    #[test]
+    fn version_2_ranks_are_migrated_to_levels() {
+        let mut buf = Vec::new();
+        buf.extend(MAGIC);
+        buf.push(2);
+        for dimension in [1u16, 1, 1] {
+            buf.write_u16::<BigEndian>(dimension).unwrap();
+        }
+        for coordinate in [0i32, 64, 0] {
+            buf.write_i32::<BigEndian>(coordinate).unwrap();
+        }
+        buf.extend([0, 0]);
+        write_string(&mut buf, "Old world").unwrap();
+        buf.write_u16::<BigEndian>(2).unwrap();
+        // Pairs of block IDs and access, where 0 was everyone, 1 was operators, and 2 was nobody
+        section(&mut buf, BLOCK_ACCESS_TAG, &[1, 0, 7, 1, 46, 2]);
+        let mut permissions = Vec::new();
+        permissions.push(1);
+        write_string(&mut permissions, "owner").unwrap();
+        permissions.write_u16::<BigEndian>(0).unwrap();
+        // Visit and build, where 0 was guest, 1 was operator, and 2 was nobody
+        permissions.extend([1, 2]);
+        section(&mut buf, PERMISSIONS_TAG, &permissions);
+        buf.write_u64::<BigEndian>(1).unwrap();
+        let mut encoder = GzEncoder::new(&mut buf, Compression::fast());
+        encoder.write_all(&[1]).unwrap();
+        encoder.finish().unwrap();
+
+        let world = WorldData::load(buf.as_slice()).unwrap();
+        assert_eq!(world.block_access.get(&1), Some(&RankRequirement::Level(0)));
+        assert_eq!(world.block_access.get(&7), Some(&RankRequirement::Level(ranks::OPERATOR_LEVEL)));
+        assert_eq!(world.block_access.get(&46), Some(&RankRequirement::Nobody));
+        assert_eq!(world.permissions.owner.as_deref(), Some("owner"));
+        assert_eq!(world.permissions.visit, RankRequirement::Level(ranks::OPERATOR_LEVEL));
+        assert_eq!(world.permissions.build, RankRequirement::Nobody);
+        assert_eq!(world.level_data.raw_data, [1]);
+    }
+
+    #[test]
+    fn unknown_ranks_are_rejected() {
+        let mut world = WorldData::default();
+        assert!(world.load_block_access([1u8, 3].as_slice(), 2).is_err());
+        assert!(world.load_block_access([1u8, 2, 0].as_slice(), 3).is_err());
+    }
+}
@@ -10,6 +10,12 @@ mod world;
 mod level_serde;
 mod packets;
 mod worldgen;
+mod blocks;
+mod events;
+mod effects;
+mod extensions;
+mod ranks;
+mod commands;
 
 use std::{
     error::Error,
@@ -17,10 +23,11 @@ use std::{
     process::ExitCode,
     collections::HashMap,
     fs::File,
-    io::{ErrorKind, Read, Write},
+    io::{self, ErrorKind, Read, Write},
     path::Path,
     ffi::OsStr,
     sync::Arc,
+    thread,
 };
 use std::path::PathBuf;
 use std::sync::OnceLock;
@@ -29,8 +36,9 @@ use serde::Deserialize;
 use simplelog::{ColorChoice, TerminalMode};
 use crate::{
     world::{WorldData, World},
-    server::IdleServer,
+    server::{IdleServer, RunningServer},
     structs::Config,
+    commands::CommandSender,
 };
 use dirs::data_local_dir;
 use parking_lot::{Condvar, Mutex};
@@ -173,6 +181,8 @@ async fn inner_main(path: &Path) -> Result<(), Box<dyn Error>> {
         warn!("Server will not gracefully shut down unless you do /stop.");
     }
 
+    start_console(handle.clone());
+
     {
         let mutex = Mutex::new(());
         let mut lock = mutex.lock();
@@ -211,6 +221,38 @@ async fn inner_main(path: &Path) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Runs commands typed into the terminal as the console, until the server is stopped or stdin is closed.
+///
+/// This uses its own thread, since reading stdin blocks and would otherwise keep the runtime from shutting down.
+fn start_console(server: RunningServer) {
+    let runtime = tokio::runtime::Handle::current();
+    let spawned = thread::Builder::new().name("console".into()).spawn(move || {
+        for line in io::stdin().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    warn!("Failed to read from the console: {err}");
+                    break;
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let command = line.strip_prefix('/').unwrap_or(line);
+            match runtime.block_on(commands::execute(CommandSender::Console, &server, command)) {
+                Ok(true) => break,
+                Ok(false) => {},
+                Err(err) => warn!("{err}"),
+            }
+        }
+    });
+    if let Err(err) = spawned {
+        warn!("Failed to start the console: {err}");
+        warn!("Commands can only be run in-game.");
+    }
+}
+
 fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
     let config_path = path.join("config.toml");
 
@@ -230,6 +272,7 @@ fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
     );
     
     config.path = config_path;
+    config.migrate();
     
     debug!("{config:?}");
     Ok(config)
@@ -2,10 +2,9 @@
 #![allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
 
 
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU16, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
 use fixed::{
-    FixedI8, FixedU16,
+    FixedI8, FixedI32,
     types::extra::U5
 };
 pub use mint::Vector3;
@@ -14,18 +13,8 @@ pub use mint::Vector3;
 /// Type alias for fixed point fractional i8s.
 pub type x8 = FixedI8<U5>;
 #[allow(non_camel_case_types)]
-/// Type alias for fixed point fractional u16s.
-pub type x16 = FixedU16<U5>;
-
-bitflags! {
-    /// A bitfield of supported extensions.
-    #[derive(Debug, Clone, PartialEq, Eq, Copy, Hash)]
-    pub struct SupportedExtensions: u32 {
-        const FULL_CP437 = 0x1;
-        const LONGER_MESSAGES = 0x2;
-        const EMOTE_FIX = 0x4;
-    }
-}
+/// Type alias for fixed point fractional i32s.
+pub type x32 = FixedI32<U5>;
 
 /// Packets going from the client to the server.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -52,7 +41,10 @@ pub enum Incoming {
     /// The player ID always refers to the sender, so it is left out.
     SetLocation {
         /// The player's new position and rotation.
-        location: Location
+        location: Location,
+        /// The block the player is holding.
+        /// This is only meaningful if the player supports `HeldBlock`.
+        held_block: u8
     },
     /// Sent when a chat message is sent.
     Message {
@@ -61,20 +53,47 @@ pub enum Incoming {
         /// Whether this chat message should be appended to the last.
         append: bool
     },
-    /// Sent to notify the server that the player supports CPE, and which extensions it supports.
-    ExtInfoEntry {
-        /// A bitfield of supported versions.
-        supported_exts: SupportedExtensions
+    /// Sent to notify the server that the player supports CPE, followed by an `ExtEntry` for each extension.
+    ExtInfo {
+        /// The number of extensions the client supports.
+        count: u16
+    },
+    /// Sent for each extension the client supports.
+    ExtEntry {
+        /// The name of the extension.
+        name: String,
+        /// The version of the extension that the client supports.
+        version: u32
+    },
+    /// Sent in response to the server's `CustomBlocks` support level.
+    CustomBlockSupportLevel {
+        /// The highest `CustomBlocks` support level the client supports.
+        support_level: u8
+    },
+    /// Sent to measure latency, either as a new ping or as a reply to one of the server's.
+    TwoWayPing {
+        /// Whether this is a reply to a ping sent by the server.
+        server_to_client: bool,
+        /// The data of the ping, used to match replies.
+        data: u16
+    },
+    /// Sent when the player presses or releases a mouse button.
+    PlayerClick {
+        /// The button that was clicked.
+        button: MouseButton,
+        /// Whether the button was pressed, as opposed to released.
+        pressed: bool,
+        /// The player's yaw.
+        yaw: i16,
+        /// The player's pitch.
+        pitch: i16,
+        /// The ID of the player that was clicked on, if any.
+        target_entity: Option<i8>,
+        /// The position and face of the block that was clicked on, if any.
+        target_block: Option<(Vector3<u16>, BlockFace)>
     }
 }
 
-/// A list of supported CPE extensions.
-static SUPPORTED_EXTS: Lazy<HashMap<String, (SupportedExtensions, u32)>> = Lazy::new(|| HashMap::from([
-    ("FullCP437".into(), (SupportedExtensions::FULL_CP437, 1)),
-    ("LongerMessages".into(), (SupportedExtensions::LONGER_MESSAGES, 1)),
-    ("EmoteFix".into(), (SupportedExtensions::EMOTE_FIX, 1))
-]));
-
 /// Packets going from the server to the client.
 #[repr(u8)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -93,7 +112,11 @@ pub enum Outgoing {
     /// Periodically sent to clients.
     Ping,
     /// Notifies a player of incoming level data.
-    LevelInit,
+    LevelInit {
+        /// The volume of the level, sent to clients that support `FastMap`.
+        /// If this is set, then the level data is sent as a raw DEFLATE stream instead of being gzipped.
+        volume: Option<u32>
+    },
     /// Contains a chunk of level data.
     LevelDataChunk {
         /// How many bytes are initialized in the chunk.
@@ -122,14 +145,20 @@ pub enum Outgoing {
         /// The player's name.
         name: String,
         /// The player's spawn position and rotation.
-        location: Location
+        location: Location,
+        /// Whether to send the location with 32-bit coordinates.
+        /// This should only be set for clients that support `ExtEntityPositions`.
+        extended: bool
     },
     /// Sent to teleport a player to a location.
     TeleportPlayer {
         /// The player's ID.
         id: i8,
         /// The player's new position and rotation.
-        location: Location
+        location: Location,
+        /// Whether to send the location with 32-bit coordinates.
+        /// This should only be set for clients that support `ExtEntityPositions`.
+        extended: bool
     },
     /// Sent to update a player's position and rotation.
     UpdatePlayerLocation {
@@ -181,25 +210,285 @@ pub enum Outgoing {
         operator: bool
     },
     /// Sent to notify players that the server supports CPE.
-    ExtInfoEntry
+    ExtInfoEntry,
+    /// Sent to notify players of the highest `CustomBlocks` support level the server supports.
+    CustomBlockSupportLevel {
+        /// The server's support level.
+        support_level: u8
+    },
+    /// Sent to define a custom block.
+    DefineBlock {
+        /// The ID of the block being defined.
+        id: u8,
+        /// The block's definition.
+        definition: BlockDefinition
+    },
+    /// Sent to remove a custom block's definition.
+    RemoveBlockDefinition {
+        /// The ID of the block being removed.
+        id: u8
+    },
+    /// Sent to define a custom block with a custom bounding box and textures on each side.
+    DefineBlockExt {
+        /// The ID of the block being defined.
+        id: u8,
+        /// The block's definition.
+        definition: BlockDefinition
+    },
+    /// Sent to add or update a name in the player list.
+    ExtAddPlayerName {
+        /// The server-wide ID of the name.
+        name_id: i16,
+        /// The player's username.
+        player_name: String,
+        /// The name shown in the player list.
+        list_name: String,
+        /// The group the name is listed under.
+        group_name: String,
+        /// The name's rank within its group. Lower ranks are listed first.
+        group_rank: u8
+    },
+    /// Sent to remove a name from the player list.
+    ExtRemovePlayerName {
+        /// The server-wide ID of the name.
+        name_id: i16
+    },
+    /// Sent to change the block a player is holding.
+    HoldThis {
+        /// The block to hold.
+        block: u8,
+        /// Whether the player should be prevented from changing the block they're holding.
+        prevent_change: bool
+    },
+    /// Sent to change whether a player can place or delete a block.
+    SetBlockPermission {
+        /// The block whose permissions are changing.
+        block: u8,
+        /// Whether the player can place the block.
+        allow_placement: bool,
+        /// Whether the player can delete the block.
+        allow_deletion: bool
+    },
+    /// Sent to put a block in a player's hotbar.
+    SetHotbar {
+        /// The block to put in the hotbar.
+        block: u8,
+        /// The index of the hotbar slot, from 0 to 8.
+        index: u8
+    },
+    /// Sent to change one of the colors of the environment.
+    EnvSetColor {
+        /// Which color to change.
+        /// 0 is the sky, 1 is the clouds, 2 is the fog, 3 is ambient light, and 4 is diffuse light.
+        variable: u8,
+        /// The new color, or `None` to reset it to the client's default.
+        color: Option<[u8; 3]>
+    },
+    /// Sent to change the weather.
+    EnvSetWeatherType {
+        /// The new weather. 0 is sunny, 1 is raining, and 2 is snowing.
+        weather: u8
+    },
+    /// Sent to change a property of the map's appearance.
+    SetMapEnvProperty {
+        /// Which property to change.
+        /// 0 is the side block, 1 is the edge block, and 2 is the edge height.
+        property: u8,
+        /// The property's new value.
+        value: i32
+    },
+    /// Sent instead of [`Outgoing::SpawnPlayer`] to clients that support `ExtPlayerList`.
+    ExtAddEntity2 {
+        /// The player's ID.
+        id: i8,
+        /// The name shown above the player.
+        name: String,
+        /// The name of the player's skin.
+        skin: String,
+        /// The player's spawn position and rotation.
+        location: Location,
+        /// Whether to send the location with 32-bit coordinates.
+        /// This should only be set for clients that support `ExtEntityPositions`.
+        extended: bool
+    },
+    /// Sent to measure latency, either as a new ping or as a reply to one of the client's.
+    TwoWayPing {
+        /// Whether this is a new ping sent by the server.
+        server_to_client: bool,
+        /// The data of the ping, used to match replies.
+        data: u16
+    },
+    /// Sent to show a selected region to the player.
+    MakeSelection {
+        /// The selection's ID.
+        id: u8,
+        /// The selected region.
+        selection: Selection
+    },
+    /// Sent to stop showing a selected region to the player.
+    RemoveSelection {
+        /// The selection's ID.
+        id: u8
+    },
+    /// Sent to change how a player looks.
+    ChangeModel {
+        /// The player's ID.
+        id: i8,
+        /// The name of the model, or a block ID.
+        model: String
+    },
+    /// Sent to change a property of a player, such as their scale.
+    SetEntityProperty {
+        /// The player's ID.
+        id: i8,
+        /// Which property to change.
+        /// 0 to 2 are the X, Y, and Z rotation, and 3 to 5 are the X, Y, and Z scale.
+        property: u8,
+        /// The property's new value. Scales are in thousandths.
+        value: i32
+    },
+    /// Sent to control which hacks the player can use.
+    HackControl {
+        /// Whether the player can fly.
+        flying: bool,
+        /// Whether the player can walk through blocks.
+        noclip: bool,
+        /// Whether the player can move faster.
+        speeding: bool,
+        /// Whether the player can respawn and set their spawn point.
+        respawn: bool,
+        /// Whether the player can use third person view.
+        third_person: bool,
+        /// How high the player can jump, in 32nds of a block. -1 uses the default height.
+        jump_height: i16
+    },
+    /// Sent to change how far away the player can place and break blocks.
+    SetClickDistance {
+        /// The player's reach, in 32nds of a block.
+        distance: i16
+    },
+    /// Sent to bind a key to send a message or command.
+    SetTextHotKey {
+        /// The name of the hotkey.
+        label: String,
+        /// The text sent when the key is pressed. If this is empty, then the hotkey is removed.
+        action: String,
+        /// The LWJGL key code of the key.
+        key_code: i32,
+        /// A bitfield of the modifier keys to hold. 1 is Ctrl, 2 is Shift, and 4 is Alt.
+        modifiers: u8
+    },
+    /// Sent to move a block in the player's inventory.
+    SetInventoryOrder {
+        /// The block to move.
+        block: u8,
+        /// The block's new position in the inventory, starting at 1. If this is 0, then the block is hidden.
+        order: u8
+    },
+    /// Sent to define a custom particle effect.
+    DefineEffect {
+        /// The ID of the effect being defined.
+        id: u8,
+        /// The effect's definition.
+        effect: ParticleEffect
+    },
+    /// Sent to spawn a custom particle effect.
+    SpawnEffect {
+        /// The ID of the effect.
+        id: u8,
+        /// Where the particles are spawned.
+        position: Vector3<x32>,
+        /// The point the particles move away from.
+        origin: Vector3<x32>
+    },
+    /// Sent to set where the player goes when they respawn.
+    SetSpawnpoint {
+        /// The player's new spawn point.
+        location: Location,
+        /// Whether to send the position with 32-bit coordinates, as used by `ExtEntityPositions`.
+        extended: bool
+    },
+    /// Sent to change the player's velocity.
+    VelocityControl {
+        /// The change in velocity, in ten-thousandths of a block per tick.
+        velocity: Vector3<i32>,
+        /// Whether each axis replaces the player's velocity, instead of adding to it.
+        replace: [bool; 3]
+    },
+    /// Sent to change many blocks at once.
+    BulkBlockUpdate {
+        /// The indices of the blocks in the level data, and their new IDs. There must be between 1 and 256 of these.
+        changes: Vec<(i32, u8)>
+    }
 }
 
+/// Where a message is shown on a player's screen.
+/// Anything other than chat requires the `MessageTypes` extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MessageType {
+    /// A normal chat message.
+    #[default]
+    Chat,
+    /// One of the three status lines in the top right of the screen, from 1 to 3.
+    Status(u8),
+    /// One of the three lines in the bottom right of the screen, from 1 to 3.
+    BottomRight(u8),
+    /// A large message in the center of the screen.
+    Announcement,
+}
+
+impl MessageType {
+    /// Gets the ID of the message type, sent in place of the player ID of a message.
+    #[must_use]
+    pub fn id(self) -> i8 {
+        match self {
+            MessageType::Chat => 0,
+            MessageType::Status(line) => line.clamp(1, 3) as i8,
+            MessageType::BottomRight(line) => 10 + line.clamp(1, 3) as i8,
+            MessageType::Announcement => 100,
+        }
+    }
+
+    /// Gets the message type that should be shown to clients that don't support `MessageTypes`,
+    /// or `None` if the message should be dropped.
+    #[must_use]
+    pub fn fallback(self) -> Option<MessageType> {
+        match self {
+            MessageType::Chat | MessageType::Announcement => Some(MessageType::Chat),
+            MessageType::Status(_) | MessageType::BottomRight(_) => None,
+        }
+    }
+}
 
 /// A single player's position and rotation.
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
 pub struct Location {
     /// The player's position.
-    pub position: Vector3<x16>,
+    pub position: Vector3<x32>,
     /// The player's yaw.
     pub yaw: u8,
     /// The player's pitch.
     pub pitch: u8
 }
 
+impl Location {
+    /// Gets the position of the block that the player's feet are in.
+    #[must_use]
+    pub fn block_position(&self) -> Vector3<i32> {
+        // Positions are sent at eye level, 51/32 blocks above the feet
+        let feet = self.position.y - x32::from_bits(51);
+        Vector3 {
+            x: self.position.x.to_num(),
+            y: feet.to_num(),
+            z: self.position.z.to_num()
+        }
+    }
+}
+
 impl Default for Location {
     fn default() -> Self {
         Self {
-            position: Vector3 { x: 0.into(), y: 0.into(), z: 0.into() },
+            position: Vector3 { x: x32::ZERO, y: x32::ZERO, z: x32::ZERO },
             yaw: 0,
             pitch: 0
         }
@@ -210,7 +499,7 @@ impl Default for Location {
 #[derive(Debug)]
 pub struct AtomicLocation {
     /// The player's position.
-    pub position: Vector3<AtomicU16>,
+    pub position: Vector3<AtomicI32>,
     /// The player's yaw.
     pub yaw: AtomicU8,
     /// The player's pitch.
@@ -232,9 +521,9 @@ impl From<&AtomicLocation> for Location {
     fn from(value: &AtomicLocation) -> Self {
         Location {
             position: Vector3 {
-                x: x16::from_bits(value.position.x.load(Ordering::Relaxed)),
-                y: x16::from_bits(value.position.y.load(Ordering::Relaxed)),
-                z: x16::from_bits(value.position.z.load(Ordering::Relaxed))
+                x: x32::from_bits(value.position.x.load(Ordering::Relaxed)),
+                y: x32::from_bits(value.position.y.load(Ordering::Relaxed)),
+                z: x32::from_bits(value.position.z.load(Ordering::Relaxed))
             },
             yaw: value.yaw.load(Ordering::Relaxed),
             pitch: value.pitch.load(Ordering::Relaxed)
@@ -257,10 +546,13 @@ impl From<Location> for AtomicLocation {
 }
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-use std::io::{self, ErrorKind};
-use bitflags::bitflags;
+use std::{error::Error, fmt, io::{self, ErrorKind}};
 use codepage_437::{BorrowFromCp437, ToCp437};
-use once_cell::sync::Lazy;
+use crate::blocks::BlockDefinition;
+use crate::extensions::{Extension, SupportedExtensions};
+use crate::effects::ParticleEffect;
+use crate::events::{BlockFace, MouseButton};
+use crate::world::Selection;
 
 /// Dictates that this type can be loaded from a packet. This trait is sealed.
 pub trait IncomingPacketType {
@@ -318,6 +610,26 @@ impl OutgoingPacketType for u16 {
     }
 }
 
+impl IncomingPacketType for i16 {
+    async fn load(mut source: impl AsyncRead + Unpin) -> io::Result<Self> {
+        let mut buf = [0, 0];
+        source.read_exact(&mut buf).await?;
+        Ok(i16::from_be_bytes(buf))
+    }
+}
+
+impl OutgoingPacketType for i16 {
+    async fn store(&self, mut destination: impl AsyncWrite + Unpin) -> io::Result<()> {
+        destination.write_all(&self.to_be_bytes()).await
+    }
+}
+
+impl OutgoingPacketType for i32 {
+    async fn store(&self, mut destination: impl AsyncWrite + Unpin) -> io::Result<()> {
+        destination.write_all(&self.to_be_bytes()).await
+    }
+}
+
 impl IncomingPacketType for u32 {
     async fn load(mut source: impl AsyncRead + Unpin) -> io::Result<Self> {
         let mut buf = [0; 4];
@@ -346,15 +658,15 @@ impl OutgoingPacketType for x8 {
     }
 }
 
-impl IncomingPacketType for x16 {
+impl IncomingPacketType for x32 {
     async fn load(mut source: impl AsyncRead + Unpin) -> io::Result<Self> {
-        let mut buf = [0, 0];
+        let mut buf = [0; 4];
         source.read_exact(&mut buf).await?;
-        Ok(x16::from_be_bytes(buf))
+        Ok(x32::from_be_bytes(buf))
     }
 }
 
-impl OutgoingPacketType for x16 {
+impl OutgoingPacketType for x32 {
     async fn store(&self, mut destination: impl AsyncWrite + Unpin) -> io::Result<()> {
         destination.write_all(&self.to_be_bytes()).await
     }
@@ -378,19 +690,41 @@ impl<T: OutgoingPacketType> OutgoingPacketType for Vector3<T> {
     }
 }
 
-impl IncomingPacketType for Location {
-    async fn load(mut source: impl AsyncRead + Unpin) -> io::Result<Self> {
+impl Location {
+    /// Loads a location from a packet.
+    /// If `extended` is set, then the position is read with 32-bit coordinates, as used by `ExtEntityPositions`.
+    #[allow(clippy::missing_errors_doc)]
+    pub async fn load_with(mut source: impl AsyncRead + Unpin, extended: bool) -> io::Result<Self> {
+        let position = if extended {
+            Vector3::<x32>::load(&mut source).await?
+        } else {
+            let Vector3 { x, y, z } = Vector3::<i16>::load(&mut source).await?;
+            Vector3 {
+                x: x32::from_bits(x.into()),
+                y: x32::from_bits(y.into()),
+                z: x32::from_bits(z.into())
+            }
+        };
         Ok(Location {
-            position: Vector3::<x16>::load(&mut source).await?,
+            position,
             yaw: u8::load(&mut source).await?,
             pitch: u8::load(source).await?,
-        } )
+        })
     }
-}
 
-impl OutgoingPacketType for Location {
-    async fn store(&self, mut destination: impl AsyncWrite + Unpin) -> io::Result<()> {
-        self.position.store(&mut destination).await?;
+    /// Stores a location in a packet.
+    /// If `extended` is set, then the position is written with 32-bit coordinates, as used by `ExtEntityPositions`.
+    /// Otherwise, coordinates that don't fit in 16 bits are clamped.
+    #[allow(clippy::missing_errors_doc, clippy::cast_possible_truncation)]
+    pub async fn store_with(&self, mut destination: impl AsyncWrite + Unpin, extended: bool) -> io::Result<()> {
+        if extended {
+            self.position.store(&mut destination).await?;
+        } else {
+            for coordinate in [self.position.x, self.position.y, self.position.z] {
+                let bits = coordinate.to_bits().clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+                bits.store(&mut destination).await?;
+            }
+        }
         self.yaw.store(&mut destination).await?;
         self.pitch.store(destination).await
     }
@@ -433,10 +767,135 @@ impl<const SIZE: usize> OutgoingPacketType for [u8; SIZE] {
     }
 }
 
-impl IncomingPacketType for Incoming {
-    async fn load(mut source: impl AsyncRead + Unpin) -> io::Result<Self> {
-        let discriminant = u8::load(&mut source).await?;
-        Ok(match discriminant {
+/// The size of the largest packet a client can send, not counting its ID.
+const MAX_INCOMING_SIZE: usize = 130;
+
+/// Gets the size of a packet sent by a client with the given extensions, not counting its ID,
+/// or `None` if the packet is unknown.
+///
+/// This includes packets that the server doesn't handle, so that they can be skipped.
+#[must_use]
+#[allow(clippy::match_same_arms)] // Kept as one arm per packet
+pub fn incoming_size(id: u8, extensions: SupportedExtensions) -> Option<usize> {
+    Some(match id {
+        0x00 => 130, // PlayerIdentification
+        0x05 => 8,   // SetBlock
+        0x08 if extensions.contains(Extension::ExtEntityPositions) => 15, // SetLocation
+        0x08 => 9,   // SetLocation
+        0x0d => 65,  // Message
+        0x10 => 66,  // ExtInfo
+        0x11 => 68,  // ExtEntry
+        0x13 => 1,   // CustomBlockSupportLevel
+        0x22 => 14,  // PlayerClick
+        0x2b => 3,   // TwoWayPing
+        0x35 => 65,  // PluginMessage, which isn't supported
+        _ => return None
+    })
+}
+
+/// An error caused by a client breaking the protocol, or by the connection failing.
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The connection failed.
+    Io(io::Error),
+    /// The client sent a packet with an unknown ID, so it couldn't be skipped.
+    UnknownPacket(u8),
+    /// The client sent a packet that couldn't be decoded.
+    InvalidPacket {
+        /// The ID of the packet.
+        id: u8,
+        /// Why the packet is invalid.
+        reason: String
+    },
+    /// The client sent a valid packet at the wrong time.
+    UnexpectedPacket {
+        /// The packet that was expected.
+        expected: &'static str,
+        /// The ID of the packet that was sent instead.
+        got: u8
+    },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(err) if err.kind() == ErrorKind::UnexpectedEof => write!(f, "Connection closed"),
+            ProtocolError::Io(err) => write!(f, "Connection died: {err}"),
+            ProtocolError::UnknownPacket(id) => write!(f, "Unknown packet 0x{id:02x}"),
+            ProtocolError::InvalidPacket { id, reason } => write!(f, "Invalid packet 0x{id:02x}: {reason}"),
+            ProtocolError::UnexpectedPacket { expected, got } => write!(f, "Expected {expected}, got packet 0x{got:02x}"),
+        }
+    }
+}
+
+impl Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProtocolError::Io(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(err: io::Error) -> Self {
+        ProtocolError::Io(err)
+    }
+}
+
+impl Incoming {
+    /// Gets the ID that the packet was sent with.
+    #[must_use]
+    pub fn id(&self) -> u8 {
+        match self {
+            Incoming::PlayerIdentification { .. } => 0x00,
+            Incoming::SetBlock { .. } => 0x05,
+            Incoming::SetLocation { .. } => 0x08,
+            Incoming::Message { .. } => 0x0d,
+            Incoming::ExtInfo { .. } => 0x10,
+            Incoming::ExtEntry { .. } => 0x11,
+            Incoming::CustomBlockSupportLevel { .. } => 0x13,
+            Incoming::PlayerClick { .. } => 0x22,
+            Incoming::TwoWayPing { .. } => 0x2b,
+        }
+    }
+
+    /// Loads a packet from a client that doesn't support any extensions.
+    #[allow(clippy::missing_errors_doc)]
+    pub async fn load(source: impl AsyncRead + Unpin) -> Result<Self, ProtocolError> {
+        Incoming::load_with(source, SupportedExtensions::empty()).await
+    }
+
+    /// Loads a packet from a client that supports the given extensions.
+    ///
+    /// Packets that are known but not handled, or that belong to extensions that weren't negotiated, are skipped.
+    #[allow(clippy::missing_errors_doc)]
+    pub async fn load_with(mut source: impl AsyncRead + Unpin, extensions: SupportedExtensions) -> Result<Self, ProtocolError> {
+        loop {
+            let id = u8::load(&mut source).await?;
+            let size = incoming_size(id, extensions).ok_or(ProtocolError::UnknownPacket(id))?;
+            // Read the whole packet first, so a bad packet can't leave the stream halfway through it
+            let mut buf = [0; MAX_INCOMING_SIZE];
+            let body = &mut buf[..size];
+            source.read_exact(body).await?;
+
+            if let Some(extension) = Extension::for_packet(id) {
+                if !extensions.contains(extension) {
+                    warn!("Skipping packet 0x{id:02x} from {}, which wasn't negotiated", extension.info().name);
+                    continue;
+                }
+            }
+            match Incoming::parse(id, body, extensions).await {
+                Ok(Some(packet)) => return Ok(packet),
+                Ok(None) => debug!("Skipping unhandled packet 0x{id:02x}"),
+                Err(err) => return Err(ProtocolError::InvalidPacket { id, reason: err.to_string() })
+            }
+        }
+    }
+
+    /// Parses the body of a packet, or returns `None` if the packet isn't handled.
+    async fn parse(id: u8, mut source: &[u8], extensions: SupportedExtensions) -> io::Result<Option<Self>> {
+        Ok(Some(match id {
             0x00 => Incoming::PlayerIdentification {
                 version: u8::load(&mut source).await?,
                 username: String::load(&mut source).await?,
@@ -452,11 +911,11 @@ impl IncomingPacketType for Incoming {
                     state: if mode {id} else {0}
                 }
             },
-            0x08 => {
-                let _ = u8::load(&mut source).await?;
-                Incoming::SetLocation {
-                    location: Location::load(&mut source).await?
-                }
+            0x08 => Incoming::SetLocation {
+                held_block: u8::load(&mut source).await?,
+                location: Location::load_with(
+                    &mut source, extensions.contains(Extension::ExtEntityPositions)
+                ).await?
             },
             0x0d => Incoming::Message {
                 append: u8::load(&mut source).await? == 1,
@@ -464,36 +923,102 @@ impl IncomingPacketType for Incoming {
             },
             0x10 => {
                 let _ = String::load(&mut source).await?; // We don't care what the client is
-                let count = u16::load(&mut source).await?;
-                debug!("Client supports {count}");
-                let mut supported_exts = SupportedExtensions::empty();
-                for _ in 0..count {
-                    let id = u8::load(&mut source).await?;
-                    if id != 0x11 {
-                        return Err(
-                            io::Error::new(ErrorKind::InvalidData, format!("Expected ExtEntry (0x11), got 0x{id:02x}"))
-                        )
-                    }
-                    let name = String::load(&mut source).await?;
-                    let their_version = u32::load(&mut source).await?;
-                    debug!("Client supports extension \"{name}\" with version {their_version}");
-                    let Some((mask, our_version)) = SUPPORTED_EXTS.get(&name)
-                        else { continue };
-                    if *our_version == their_version {
-                        supported_exts |= *mask;
-                    }
+                Incoming::ExtInfo {
+                    count: u16::load(source).await?
                 }
-                Incoming::ExtInfoEntry { supported_exts }
             },
-            dis => return Err(
-                io::Error::new(ErrorKind::InvalidData, format!("Invalid packet discriminator 0x{dis:02x}"))
-            )
-        })
+            0x11 => Incoming::ExtEntry {
+                name: String::load(&mut source).await?,
+                version: u32::load(source).await?
+            },
+            0x13 => Incoming::CustomBlockSupportLevel {
+                support_level: u8::load(source).await?
+            },
+            0x2b => Incoming::TwoWayPing {
+                server_to_client: u8::load(&mut source).await? != 0,
+                data: u16::load(source).await?
+            },
+            0x22 => {
+                let button = u8::load(&mut source).await?;
+                let button = MouseButton::try_from(button).map_err(|button|
+                    io::Error::new(ErrorKind::InvalidData, format!("Invalid mouse button {button}"))
+                )?;
+                let pressed = u8::load(&mut source).await? == 0;
+                let yaw = i16::load(&mut source).await?;
+                let pitch = i16::load(&mut source).await?;
+                let target_entity = u8::load(&mut source).await?;
+                let target_position = Vector3::<u16>::load(&mut source).await?;
+                let target_face = u8::load(source).await?;
+                Incoming::PlayerClick {
+                    button,
+                    pressed,
+                    yaw,
+                    pitch,
+                    // An ID of 255 means no player was clicked on
+                    target_entity: (target_entity != u8::MAX).then_some(target_entity as i8),
+                    // Invalid faces mean no block was clicked on
+                    target_block: BlockFace::try_from(target_face).ok().map(|face| (target_position, face))
+                }
+            },
+            _ => return Ok(None)
+        }))
+    }
+}
+
+impl Outgoing {
+    /// Gets the ID that the packet is sent with.
+    #[must_use]
+    pub fn id(&self) -> u8 {
+        match self {
+            Outgoing::ServerIdentification { .. } => 0x00,
+            Outgoing::Ping => 0x01,
+            Outgoing::LevelInit { .. } => 0x02,
+            Outgoing::LevelDataChunk { .. } => 0x03,
+            Outgoing::LevelFinalize { .. } => 0x04,
+            Outgoing::SetBlock { .. } => 0x06,
+            Outgoing::SpawnPlayer { .. } => 0x07,
+            Outgoing::TeleportPlayer { .. } => 0x08,
+            Outgoing::UpdatePlayerLocation { .. } => 0x09,
+            Outgoing::UpdatePlayerPosition { .. } => 0x0a,
+            Outgoing::UpdatePlayerRotation { .. } => 0x0b,
+            Outgoing::DespawnPlayer { .. } => 0x0c,
+            Outgoing::Message { .. } => 0x0d,
+            Outgoing::Disconnect { .. } => 0x0e,
+            Outgoing::UpdateUser { .. } => 0x0f,
+            Outgoing::ExtInfoEntry => 0x10,
+            Outgoing::CustomBlockSupportLevel { .. } => 0x13,
+            Outgoing::DefineBlock { .. } => 0x23,
+            Outgoing::RemoveBlockDefinition { .. } => 0x24,
+            Outgoing::DefineBlockExt { .. } => 0x25,
+            Outgoing::ExtAddPlayerName { .. } => 0x16,
+            Outgoing::ExtRemovePlayerName { .. } => 0x18,
+            Outgoing::HoldThis { .. } => 0x14,
+            Outgoing::SetBlockPermission { .. } => 0x1c,
+            Outgoing::SetHotbar { .. } => 0x2d,
+            Outgoing::EnvSetColor { .. } => 0x19,
+            Outgoing::EnvSetWeatherType { .. } => 0x1f,
+            Outgoing::SetMapEnvProperty { .. } => 0x29,
+            Outgoing::ExtAddEntity2 { .. } => 0x21,
+            Outgoing::TwoWayPing { .. } => 0x2b,
+            Outgoing::MakeSelection { .. } => 0x1a,
+            Outgoing::RemoveSelection { .. } => 0x1b,
+            Outgoing::ChangeModel { .. } => 0x1d,
+            Outgoing::SetEntityProperty { .. } => 0x2a,
+            Outgoing::HackControl { .. } => 0x20,
+            Outgoing::SetClickDistance { .. } => 0x12,
+            Outgoing::SetTextHotKey { .. } => 0x15,
+            Outgoing::SetInventoryOrder { .. } => 0x2c,
+            Outgoing::DefineEffect { .. } => 0x30,
+            Outgoing::SpawnEffect { .. } => 0x31,
+            Outgoing::SetSpawnpoint { .. } => 0x2e,
+            Outgoing::VelocityControl { .. } => 0x2f,
+            Outgoing::BulkBlockUpdate { .. } => 0x26,
+        }
     }
 }
 
 impl OutgoingPacketType for Outgoing {
-    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_truncation, clippy::too_many_lines)]
     async fn store(&self, mut destination: impl AsyncWrite + Unpin) -> io::Result<()> {
         match self {
             Outgoing::ServerIdentification { version, name, motd, operator } => {
@@ -504,7 +1029,11 @@ impl OutgoingPacketType for Outgoing {
                 (if *operator { 0x64u8 } else { 0x00u8 }).store(destination).await
             },
             Outgoing::Ping => 0x1u8.store(destination).await,
-            Outgoing::LevelInit => 0x2u8.store(destination).await,
+            Outgoing::LevelInit { volume: None } => 0x2u8.store(destination).await,
+            Outgoing::LevelInit { volume: Some(volume) } => {
+                0x2u8.store(&mut destination).await?;
+                volume.store(destination).await
+            },
             Outgoing::LevelDataChunk { data_length, data_chunk, percent_complete } => {
                 0x3u8.store(&mut destination).await?;
                 data_length.store(&mut destination).await?;
@@ -520,16 +1049,16 @@ impl OutgoingPacketType for Outgoing {
                 position.store(&mut destination).await?;
                 block.store(destination).await
             },
-            Outgoing::SpawnPlayer { id, name, location } => {
+            Outgoing::SpawnPlayer { id, name, location, extended } => {
                 0x7u8.store(&mut destination).await?;
                 id.store(&mut destination).await?;
                 name.store(&mut destination).await?;
-                location.store(&mut destination).await
+                location.store_with(&mut destination, *extended).await
             },
-            Outgoing::TeleportPlayer { id, location } => {
+            Outgoing::TeleportPlayer { id, location, extended } => {
                 0x8u8.store(&mut destination).await?;
                 id.store(&mut destination).await?;
-                location.store(&mut destination).await
+                location.store_with(&mut destination, *extended).await
             },
             Outgoing::UpdatePlayerLocation { id, position_change, yaw, pitch } => {
                 0x9u8.store(&mut destination).await?;
@@ -569,14 +1098,216 @@ impl OutgoingPacketType for Outgoing {
             Outgoing::ExtInfoEntry => {
                 0x10u8.store(&mut destination).await?;
                 "Honeybit".to_string().store(&mut destination).await?;
-                (SUPPORTED_EXTS.len() as u16).store(&mut destination).await?;
-                for (name, (_, version)) in SUPPORTED_EXTS.iter() {
+                (Extension::ALL.len() as u16).store(&mut destination).await?;
+                for extension in Extension::ALL {
+                    let info = extension.info();
+                    let version = *info.versions.end();
                     0x11u8.store(&mut destination).await?;
-                    name.to_string().store(&mut destination).await?;
-                    (*version).store(&mut destination).await?;
-                    debug!("Server supports extension \"{name}\" with version {version}");
+                    info.name.to_string().store(&mut destination).await?;
+                    version.store(&mut destination).await?;
+                    debug!("Server supports extension \"{}\" with version {version}", info.name);
                 }
                 Ok(())
+            },
+            Outgoing::CustomBlockSupportLevel { support_level } => {
+                0x13u8.store(&mut destination).await?;
+                support_level.store(destination).await
+            },
+            Outgoing::DefineBlock { id, definition } => {
+                let [top, side, .., bottom] = definition.textures;
+                0x23u8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                definition.name.store(&mut destination).await?;
+                definition.solidity.store(&mut destination).await?;
+                definition.speed.store(&mut destination).await?;
+                top.store(&mut destination).await?;
+                side.store(&mut destination).await?;
+                bottom.store(&mut destination).await?;
+                u8::from(definition.transmits_light).store(&mut destination).await?;
+                definition.walk_sound.store(&mut destination).await?;
+                u8::from(definition.full_bright).store(&mut destination).await?;
+                (if definition.sprite { 0 } else { definition.max.y }).store(&mut destination).await?;
+                definition.draw.store(&mut destination).await?;
+                definition.fog_density.store(&mut destination).await?;
+                definition.fog_color.store(destination).await
+            },
+            Outgoing::RemoveBlockDefinition { id } => {
+                0x24u8.store(&mut destination).await?;
+                id.store(destination).await
+            },
+            Outgoing::DefineBlockExt { id, definition } => {
+                0x25u8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                definition.name.store(&mut destination).await?;
+                definition.solidity.store(&mut destination).await?;
+                definition.speed.store(&mut destination).await?;
+                definition.textures.store(&mut destination).await?;
+                u8::from(definition.transmits_light).store(&mut destination).await?;
+                definition.walk_sound.store(&mut destination).await?;
+                u8::from(definition.full_bright).store(&mut destination).await?;
+                definition.min.store(&mut destination).await?;
+                definition.max.store(&mut destination).await?;
+                definition.draw.store(&mut destination).await?;
+                definition.fog_density.store(&mut destination).await?;
+                definition.fog_color.store(destination).await
+            },
+            Outgoing::ExtAddPlayerName { name_id, player_name, list_name, group_name, group_rank } => {
+                0x16u8.store(&mut destination).await?;
+                name_id.store(&mut destination).await?;
+                player_name.store(&mut destination).await?;
+                list_name.store(&mut destination).await?;
+                group_name.store(&mut destination).await?;
+                group_rank.store(destination).await
+            },
+            Outgoing::ExtRemovePlayerName { name_id } => {
+                0x18u8.store(&mut destination).await?;
+                name_id.store(destination).await
+            },
+            Outgoing::HoldThis { block, prevent_change } => {
+                0x14u8.store(&mut destination).await?;
+                block.store(&mut destination).await?;
+                u8::from(*prevent_change).store(destination).await
+            },
+            Outgoing::SetBlockPermission { block, allow_placement, allow_deletion } => {
+                0x1cu8.store(&mut destination).await?;
+                block.store(&mut destination).await?;
+                u8::from(*allow_placement).store(&mut destination).await?;
+                u8::from(*allow_deletion).store(destination).await
+            },
+            Outgoing::SetHotbar { block, index } => {
+                0x2du8.store(&mut destination).await?;
+                block.store(&mut destination).await?;
+                index.store(destination).await
+            },
+            Outgoing::EnvSetColor { variable, color } => {
+                0x19u8.store(&mut destination).await?;
+                variable.store(&mut destination).await?;
+                let channels = color.map_or([-1; 3], |color| color.map(i16::from));
+                for channel in channels {
+                    channel.store(&mut destination).await?;
+                }
+                Ok(())
+            },
+            Outgoing::EnvSetWeatherType { weather } => {
+                0x1fu8.store(&mut destination).await?;
+                weather.store(destination).await
+            },
+            Outgoing::SetMapEnvProperty { property, value } => {
+                0x29u8.store(&mut destination).await?;
+                property.store(&mut destination).await?;
+                value.store(destination).await
+            },
+            Outgoing::ExtAddEntity2 { id, name, skin, location, extended } => {
+                0x21u8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                name.store(&mut destination).await?;
+                skin.store(&mut destination).await?;
+                location.store_with(destination, *extended).await
+            }
+            Outgoing::TwoWayPing { server_to_client, data } => {
+                0x2bu8.store(&mut destination).await?;
+                u8::from(*server_to_client).store(&mut destination).await?;
+                data.store(destination).await
+            }
+            Outgoing::MakeSelection { id, selection } => {
+                0x1au8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                selection.label.store(&mut destination).await?;
+                selection.start().store(&mut destination).await?;
+                // The end corner is exclusive
+                let end = selection.end();
+                for coordinate in [end.x, end.y, end.z] {
+                    coordinate.saturating_add(1).store(&mut destination).await?;
+                }
+                for channel in selection.color {
+                    i16::from(channel).store(&mut destination).await?;
+                }
+                i16::from(selection.opacity).store(destination).await
+            }
+            Outgoing::RemoveSelection { id } => {
+                0x1bu8.store(&mut destination).await?;
+                id.store(destination).await
+            }
+            Outgoing::ChangeModel { id, model } => {
+                0x1du8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                model.store(destination).await
+            }
+            Outgoing::SetEntityProperty { id, property, value } => {
+                0x2au8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                property.store(&mut destination).await?;
+                value.store(destination).await
+            }
+            Outgoing::HackControl { flying, noclip, speeding, respawn, third_person, jump_height } => {
+                0x20u8.store(&mut destination).await?;
+                for flag in [flying, noclip, speeding, respawn, third_person] {
+                    u8::from(*flag).store(&mut destination).await?;
+                }
+                jump_height.store(destination).await
+            }
+            Outgoing::SetClickDistance { distance } => {
+                0x12u8.store(&mut destination).await?;
+                distance.store(destination).await
+            }
+            Outgoing::SetTextHotKey { label, action, key_code, modifiers } => {
+                0x15u8.store(&mut destination).await?;
+                label.store(&mut destination).await?;
+                action.store(&mut destination).await?;
+                key_code.store(&mut destination).await?;
+                modifiers.store(destination).await
+            }
+            Outgoing::SetInventoryOrder { block, order } => {
+                0x2cu8.store(&mut destination).await?;
+                block.store(&mut destination).await?;
+                order.store(destination).await
+            }
+            Outgoing::DefineEffect { id, effect } => {
+                0x30u8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                effect.texture_min.store(&mut destination).await?;
+                effect.texture_max.store(&mut destination).await?;
+                effect.tint.store(&mut destination).await?;
+                effect.frame_count.store(&mut destination).await?;
+                effect.particle_count.store(&mut destination).await?;
+                effect.size.store(&mut destination).await?;
+                effect.size_variation.store(&mut destination).await?;
+                effect.spread.store(&mut destination).await?;
+                effect.speed.store(&mut destination).await?;
+                effect.gravity.store(&mut destination).await?;
+                effect.lifetime.store(&mut destination).await?;
+                effect.lifetime_variation.store(&mut destination).await?;
+                effect.collide_flags.store(&mut destination).await?;
+                u8::from(effect.full_bright).store(destination).await
+            }
+            Outgoing::SpawnEffect { id, position, origin } => {
+                0x31u8.store(&mut destination).await?;
+                id.store(&mut destination).await?;
+                position.store(&mut destination).await?;
+                origin.store(destination).await
+            }
+            Outgoing::SetSpawnpoint { location, extended } => {
+                0x2eu8.store(&mut destination).await?;
+                location.store_with(destination, *extended).await
+            }
+            Outgoing::VelocityControl { velocity, replace } => {
+                0x2fu8.store(&mut destination).await?;
+                velocity.store(&mut destination).await?;
+                replace.map(u8::from).store(destination).await
+            }
+            Outgoing::BulkBlockUpdate { changes } => {
+                let (mut indices, mut blocks) = ([0; 256], [0; 256]);
+                for (i, (index, block)) in changes.iter().enumerate().take(256) {
+                    indices[i] = *index;
+                    blocks[i] = *block;
+                }
+                0x26u8.store(&mut destination).await?;
+                #[allow(clippy::cast_possible_truncation)]
+                ((changes.len().clamp(1, 256) - 1) as u8).store(&mut destination).await?;
+                for index in indices {
+                    index.store(&mut destination).await?;
+                }
+                blocks.store(destination).await
             }
         }
     }
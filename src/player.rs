@@ -3,19 +3,18 @@ use std::{
     io::{self, ErrorKind},
     sync::{
         Arc,
-        atomic::AtomicI8,
+        atomic::{AtomicI8, AtomicI16},
         atomic::Ordering,
     },
-    time::Duration,
-    sync::atomic::AtomicBool,
-    sync::Weak
+    time::{Duration, Instant},
+    sync::atomic::{AtomicBool, AtomicU8},
+    sync::Weak,
+    collections::{BTreeMap, VecDeque}
 };
 use std::sync::OnceLock;
 use codepage_437::{FromCp437, CP437_WINGDINGS, ToCp437, Cp437Error};
-use itertools::Itertools;
 use crate::{
     packets::{
-        IncomingPacketType as _,
         OutgoingPacketType as _,
         Incoming,
         Outgoing,
@@ -23,7 +22,11 @@ use crate::{
         Location
     },
     packets::AtomicLocation,
-    server::RunningServer,
+    server::{RunningServer, TabListEntry},
+    blocks::{self, BlockDefinition},
+    events::ClickEvent,
+    effects,
+    commands::{self, CommandSender},
     world::World
 };
 use tokio::{
@@ -33,8 +36,10 @@ use tokio::{
 };
 use uuid::Uuid;
 use parking_lot::Mutex;
-use crate::packets::{SupportedExtensions, x16};
-use crate::world::{LevelData, WorldData};
+use crate::packets::{MessageType, ProtocolError, x32};
+use crate::extensions::{Extension, SupportedExtensions};
+use crate::ranks;
+use crate::world::{BlockChange, BlockRejection, HackPolicy, HotKey, Selection, DEFAULT_REACH};
 
 #[derive(Debug)]
 pub struct Player {
@@ -42,6 +47,8 @@ pub struct Player {
     pub world: Arc<Mutex<World>>,
     /// The ID the player has in the world they're in.
     pub id: Arc<AtomicI8>,
+    /// The ID the player has in the server-wide player list.
+    pub name_id: Arc<AtomicI16>,
     /// A handle to the player's processing loop.
     pub handle: Sender<Command>,
     /// A handle to the player's block queue.
@@ -55,7 +62,21 @@ pub struct Player {
     /// The player's UUID. This is mainly used for logging.
     pub uuid: Uuid,
     /// The protocol extensions that the player supports.
-    pub supported_exts: Arc<OnceLock<SupportedExtensions>>
+    pub supported_exts: Arc<OnceLock<SupportedExtensions>>,
+    /// The `CustomBlocks` support level of the player's client.
+    pub block_support_level: Arc<OnceLock<u8>>,
+    /// Whether the player is an operator.
+    pub operator: Arc<AtomicBool>,
+    /// The level of the player's rank.
+    pub rank_level: Arc<AtomicU8>,
+    /// The block the player is holding.
+    pub held_block: Arc<AtomicU8>,
+    /// The player's measured latency.
+    pub ping: Arc<Mutex<PingStats>>,
+    /// The regions currently selected for the player, by selection ID.
+    pub selections: Arc<Mutex<BTreeMap<u8, Selection>>>,
+    /// How the player appears to others.
+    pub model: Arc<Mutex<Model>>
 }
 
 #[derive(Debug, Clone)]
@@ -64,6 +85,8 @@ pub(crate) struct WeakPlayer {
     pub world: Weak<Mutex<World>>,
     /// The ID the player has in the world they're in.
     pub id: Weak<AtomicI8>,
+    /// The ID the player has in the server-wide player list.
+    pub name_id: Weak<AtomicI16>,
     /// A handle to the player's processing loop.
     pub handle: WeakSender<Command>,
     /// A handle to the player's block queue.
@@ -77,7 +100,149 @@ pub(crate) struct WeakPlayer {
     /// The player's UUID.
     pub uuid: Uuid,
     /// The protocol extensions the player supports.
-    pub supported_exts: Weak<OnceLock<SupportedExtensions>>
+    pub supported_exts: Weak<OnceLock<SupportedExtensions>>,
+    /// The `CustomBlocks` support level of the player's client.
+    pub block_support_level: Weak<OnceLock<u8>>,
+    /// Whether the player is an operator.
+    pub operator: Weak<AtomicBool>,
+    /// The level of the player's rank.
+    pub rank_level: Weak<AtomicU8>,
+    /// The block the player is holding.
+    pub held_block: Weak<AtomicU8>,
+    /// The player's measured latency.
+    pub ping: Weak<Mutex<PingStats>>,
+    /// The regions currently selected for the player, by selection ID.
+    pub selections: Weak<Mutex<BTreeMap<u8, Selection>>>,
+    /// How the player appears to others.
+    pub model: Weak<Mutex<Model>>
+}
+
+/// How a player appears to others, set with `ChangeModel` and `EntityProperty`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Model {
+    /// The name of the model, or a block ID.
+    pub name: String,
+    /// The player's scale, in thousandths.
+    pub scale: i32
+}
+
+impl Model {
+    /// The names of the models that every client supports.
+    pub const NAMES: [&'static str; 11] = [
+        "humanoid", "chicken", "creeper", "pig", "sheep", "skeleton", "spider", "zombie", "sit", "head", "chibi"
+    ];
+
+    /// Gets the packets that show the model on a player with the given ID, for a client with the given extensions.
+    #[must_use]
+    pub fn packets(&self, id: i8, extensions: SupportedExtensions) -> Vec<Outgoing> {
+        let mut packets = Vec::new();
+        if extensions.contains(Extension::ChangeModel) {
+            packets.push(Outgoing::ChangeModel { id, model: self.name.clone() });
+        }
+        if extensions.contains(Extension::EntityProperty) {
+            for property in 3..=5 {
+                packets.push(Outgoing::SetEntityProperty { id, property, value: self.scale });
+            }
+        }
+        packets
+    }
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Self {
+            name: "humanoid".into(),
+            scale: 1000
+        }
+    }
+}
+
+/// The ID of the selection made with `/select`.
+pub const PLAYER_SELECTION: u8 = 0;
+
+/// How long the same block rejection isn't repeated to a player for, so that dragging doesn't flood their chat.
+const REJECTION_MESSAGE_SPACING: Duration = Duration::from_secs(2);
+
+/// The number of round trips kept to calculate a player's latency.
+const PING_SAMPLES: usize = 10;
+
+/// The smallest change in average latency that updates the player list.
+const LISTED_PING_STEP: Duration = Duration::from_millis(10);
+
+/// Rolling round-trip time measurements for a player, taken using `TwoWayPing`.
+#[derive(Debug, Default)]
+pub struct PingStats {
+    /// The data of each ping that hasn't been answered yet, and when it was sent, oldest first.
+    pending: VecDeque<(u16, Instant)>,
+    /// The data to send with the next ping.
+    next_data: u16,
+    /// The last few round-trip times.
+    samples: VecDeque<Duration>,
+    /// The average round-trip time last shown in the player list.
+    listed: Option<Duration>
+}
+
+impl PingStats {
+    /// Starts a new ping, returning the data to send with it.
+    /// Pings that have gone unanswered for longer than the timeout are forgotten.
+    pub fn start(&mut self, timeout: Duration) -> u16 {
+        while self.pending.front().is_some_and(|(_, sent)| sent.elapsed() > timeout) {
+            self.pending.pop_front();
+        }
+        let data = self.next_data;
+        self.next_data = self.next_data.wrapping_add(1);
+        self.pending.push_back((data, Instant::now()));
+        data
+    }
+
+    /// Finishes the pending ping with matching data, recording its round-trip time.
+    /// Returns whether a round trip was recorded.
+    pub fn finish(&mut self, data: u16) -> bool {
+        let Some(index) = self.pending.iter().position(|&(pending, _)| pending == data) else { return false };
+        let Some((_, sent)) = self.pending.remove(index) else { return false };
+        if self.samples.len() >= PING_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sent.elapsed());
+        true
+    }
+
+    /// Updates the latency shown in the player list if the average has moved far enough from it,
+    /// by at least 10ms or 10%, whichever is larger. Returns whether it was updated.
+    pub fn update_listed(&mut self) -> bool {
+        let Some(average) = self.average() else { return false };
+        if let Some(listed) = self.listed {
+            if average.abs_diff(listed) < LISTED_PING_STEP.max(listed / 10) {
+                return false;
+            }
+        }
+        self.listed = Some(average);
+        true
+    }
+
+    /// Gets the latency shown in the player list.
+    pub fn listed(&self) -> Option<Duration> {
+        self.listed
+    }
+
+    /// Gets the lowest recent round-trip time.
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    /// Gets the highest recent round-trip time.
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    /// Gets the average recent round-trip time.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn average(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
 }
 
 macro_rules! command_wrapper {
@@ -101,21 +266,64 @@ impl WeakPlayer {
         /// Sets the player's location.
         pub async fn set_location(&self, location: Location) => SetLocation;
         /// Notifies the player that another player has joined the world that they're in.
-        pub async fn notify_join(&self, id: i8, location: Location, name: String) => NotifyJoin;
+        pub async fn notify_join(&self, id: i8, location: Location, name: String, model: Model) => NotifyJoin;
         /// Notifies the player that another player has left the world that they're in.
         pub async fn notify_left(&self, id: i8) => NotifyLeave;
-        /// Sends the player a message in chat.
-        pub async fn send_message(&self, message: String) => Message;
+        /// Sends the player a message of the given type.
+        pub async fn send_typed_message(&self, message: String, kind: MessageType) => Message;
         /// Sends the player to a world.
         pub async fn send_to(&self, world: World) => SendTo;
-        /// Sets a block for the player.
-        pub async fn set_block(&self, id: u8, position: Vector3<u16>) => SetBlock;
+        /// Sets a block for the player, showing the fallback instead if the player doesn't support block definitions.
+        pub async fn set_block(&self, id: u8, position: Vector3<u16>, fallback: u8) => SetBlock;
         /// Notifies the player that another player has moved.
         pub async fn notify_move(&self, id: i8, location: Location) => NotifyMove;
         /// Notifies the player of the server's supported protocol extensions.
         pub async fn send_ext_info(&self) => NotifyExtensions;
-        /// Sets the player's operator status.
-        pub async fn notify_operator(&self, operator: bool) => SetOperator;
+        /// Notifies the player of the server's `CustomBlocks` support level.
+        pub async fn send_block_support_level(&self) => NotifyBlockSupportLevel;
+        /// Sets the level of the player's rank, and whether it makes them an operator.
+        pub async fn notify_rank(&self, level: u8, operator: bool) => SetRank;
+        /// Notifies the player that a custom block has been defined, or removed if the definition is `None`.
+        pub async fn notify_block_definition(&self, id: u8, definition: Option<BlockDefinition>) => NotifyBlockDefinition;
+        /// Adds or updates an entry in the player's player list.
+        pub async fn notify_list_add(&self, entry: TabListEntry) => NotifyListAdd;
+        /// Removes an entry from the player's player list.
+        pub async fn notify_list_remove(&self, name_id: i16) => NotifyListRemove;
+        /// Changes the block the player is holding.
+        pub async fn hold_block(&self, block: u8, prevent_change: bool) => HoldBlock;
+        /// Puts a block in one of the player's hotbar slots.
+        pub async fn set_hotbar(&self, block: u8, index: u8) => SetHotbar;
+        /// Notifies the player that the environment of their world has changed.
+        pub async fn notify_environment(&self) => UpdateEnvironment;
+        /// Replies to a ping sent by the player.
+        pub async fn pong(&self, data: u16) => Pong;
+        /// Selects a region for the player, replacing any selection with the same ID.
+        pub async fn select(&self, id: u8, selection: Selection) => MakeSelection;
+        /// Removes one of the player's selections.
+        pub async fn remove_selection(&self, id: u8) => RemoveSelection;
+        /// Notifies the player that another player's model has changed.
+        pub async fn notify_model(&self, id: i8, model: Model) => NotifyModel;
+        /// Notifies the player that the hack policy of their world has changed.
+        pub async fn notify_hacks(&self) => UpdateHacks;
+        /// Notifies the player that the reach distance of their world has changed.
+        pub async fn notify_reach(&self) => UpdateReach;
+        /// Binds one of the player's hotkeys, or unbinds it if `bound` is false.
+        pub async fn notify_hotkey(&self, hotkey: HotKey, bound: bool) => NotifyHotKey;
+        /// Notifies the player that the inventory palette of their world has changed.
+        pub async fn notify_palette(&self) => UpdatePalette;
+        /// Spawns a particle effect for the player.
+        pub async fn spawn_effect(&self, id: u8, position: Vector3<x32>, origin: Vector3<x32>) => SpawnEffect;
+        /// Notifies the player that the spawn point of their world has changed.
+        pub async fn notify_spawnpoint(&self) => UpdateSpawnpoint;
+        /// Changes the player's velocity, in ten-thousandths of a block per tick.
+        /// Each axis in `replace` sets the player's velocity on that axis instead of adding to it.
+        pub async fn launch(&self, velocity: Vector3<i32>, replace: [bool; 3]) => Launch;
+        /// Sets many blocks for the player at once, showing the fallbacks instead if the player doesn't support block definitions.
+        pub async fn set_blocks(&self, changes: Arc<[BlockChange]>) => SetBlocks;
+        /// Sends the player the whole level of the world they're in again.
+        pub async fn resend_level(&self) => ResendLevel;
+        /// Notifies the player that who can place and delete blocks in their world has changed.
+        pub async fn notify_block_access(&self) => UpdateBlockAccess;
     }
     
     /// Notifies the player that it has disconnected.
@@ -135,6 +343,103 @@ impl WeakPlayer {
             self.username.upgrade().is_none() ||
             self.location.upgrade().is_none()
     }
+
+    /// Sends the player a message in chat.
+    #[inline]
+    pub async fn send_message(&self, message: impl Into<String>) {
+        self.send_typed_message(message, MessageType::Chat).await;
+    }
+
+    /// Sets one of the player's status lines, from 1 to 3.
+    #[inline]
+    pub async fn send_status(&self, line: u8, message: impl Into<String>) {
+        self.send_typed_message(message, MessageType::Status(line)).await;
+    }
+
+    /// Sets one of the lines in the bottom right of the player's screen, from 1 to 3.
+    #[inline]
+    pub async fn send_bottom_right(&self, line: u8, message: impl Into<String>) {
+        self.send_typed_message(message, MessageType::BottomRight(line)).await;
+    }
+
+    /// Gets the protocol extensions that the player supports.
+    pub fn extensions(&self) -> SupportedExtensions {
+        self.supported_exts.upgrade()
+            .and_then(|exts| exts.get().copied())
+            .unwrap_or(SupportedExtensions::empty())
+    }
+
+    /// Gets the `CustomBlocks` support level of the player's client.
+    /// This is 0 if the client doesn't support `CustomBlocks`.
+    pub fn block_support_level(&self) -> u8 {
+        self.block_support_level.upgrade()
+            .and_then(|level| level.get().copied())
+            .unwrap_or(0)
+    }
+
+    /// Gets the highest block ID that the player's client supports.
+    pub fn max_block(&self) -> u8 {
+        if self.extensions().contains(Extension::BlockDefinitions) {
+            u8::MAX
+        } else if self.block_support_level() >= blocks::CUSTOM_BLOCK_SUPPORT_LEVEL {
+            blocks::MAX_CUSTOM_BLOCK
+        } else {
+            blocks::MAX_VANILLA_BLOCK
+        }
+    }
+
+    /// Checks if the player is an operator.
+    pub fn is_operator(&self) -> bool {
+        self.operator.upgrade().is_some_and(|operator| operator.load(Ordering::Relaxed))
+    }
+
+    /// Gets the level of the player's rank.
+    pub fn rank_level(&self) -> u8 {
+        self.rank_level.upgrade().map_or(0, |level| level.load(Ordering::Relaxed))
+    }
+
+    /// Gets the packet that applies a world's hack policy to the player.
+    /// Players that don't support `HackControl` are sent the server's identification again, with flags in the MOTD.
+    fn hacks_packet(&self, server: &RunningServer, hacks: HackPolicy) -> Outgoing {
+        let operator = self.is_operator();
+        if self.extensions().contains(Extension::HackControl) {
+            return hacks.packet(operator);
+        }
+        let (name, motd) = {
+            let lock = server.config.lock();
+            (lock.name.clone(), lock.motd.clone())
+        };
+        let flags = hacks.motd_flags();
+        if flags.is_empty() {
+            return Outgoing::ServerIdentification { version: 7, name, motd, operator };
+        }
+        // Make sure the flags aren't cut off
+        let motd: String = motd.chars().take(63usize.saturating_sub(flags.len())).collect();
+        Outgoing::ServerIdentification {
+            version: 7,
+            name,
+            motd: if motd.is_empty() { flags } else { format!("{motd} {flags}") },
+            operator,
+        }
+    }
+
+    /// Gets the player's model.
+    pub fn model(&self) -> Model {
+        self.model.upgrade().map(|model| model.lock().clone()).unwrap_or_default()
+    }
+
+    /// Gets one of the player's selections.
+    pub fn selection(&self, id: u8) -> Option<Selection> {
+        self.selections.upgrade()?.lock().get(&id).cloned()
+    }
+
+    /// Gets the block the player is holding, or `None` if their client doesn't support `HeldBlock`.
+    pub fn held_block(&self) -> Option<u8> {
+        if !self.extensions().contains(Extension::HeldBlock) {
+            return None;
+        }
+        self.held_block.upgrade().map(|block| block.load(Ordering::Relaxed))
+    }
 }
 
 impl From<&Player> for WeakPlayer {
@@ -142,13 +447,21 @@ impl From<&Player> for WeakPlayer {
         WeakPlayer {
             world: Arc::downgrade(&value.world),
             id: Arc::downgrade(&value.id),
+            name_id: Arc::downgrade(&value.name_id),
             username: Arc::downgrade(&value.username),
             location: Arc::downgrade(&value.location),
             connected: Arc::downgrade(&value.connected),
             handle: value.handle.downgrade(),
             block_handle: value.block_handle.downgrade(),
             uuid: value.uuid,
-            supported_exts: Arc::downgrade(&value.supported_exts)
+            supported_exts: Arc::downgrade(&value.supported_exts),
+            block_support_level: Arc::downgrade(&value.block_support_level),
+            operator: Arc::downgrade(&value.operator),
+            rank_level: Arc::downgrade(&value.rank_level),
+            held_block: Arc::downgrade(&value.held_block),
+            ping: Arc::downgrade(&value.ping),
+            selections: Arc::downgrade(&value.selections),
+            model: Arc::downgrade(&value.model)
         }
     }
 }
@@ -167,14 +480,35 @@ pub enum Command {
     /// Sends the player to a world.
     SendTo { world: World },
     /// Sets a client-side block for the player.
-    SetBlock { position: Vector3<u16>, id: u8 },
+    SetBlock { position: Vector3<u16>, id: u8, fallback: u8 },
     SetLocation { location: Location },
     NotifyLeave { id: i8 },
     NotifyMove { id: i8, location: Location },
-    NotifyJoin { id: i8, location: Location, name: String },
-    Message { message: String },
+    NotifyJoin { id: i8, location: Location, name: String, model: Model },
+    Message { message: String, kind: MessageType },
     NotifyExtensions,
-    SetOperator { operator: bool }
+    NotifyBlockSupportLevel,
+    SetRank { level: u8, operator: bool },
+    NotifyBlockDefinition { id: u8, definition: Option<BlockDefinition> },
+    NotifyListAdd { entry: TabListEntry },
+    NotifyListRemove { name_id: i16 },
+    HoldBlock { block: u8, prevent_change: bool },
+    SetHotbar { block: u8, index: u8 },
+    UpdateEnvironment,
+    Pong { data: u16 },
+    MakeSelection { id: u8, selection: Selection },
+    RemoveSelection { id: u8 },
+    NotifyModel { id: i8, model: Model },
+    UpdateHacks,
+    UpdateReach,
+    NotifyHotKey { hotkey: HotKey, bound: bool },
+    UpdatePalette,
+    SpawnEffect { id: u8, position: Vector3<x32>, origin: Vector3<x32> },
+    UpdateSpawnpoint,
+    Launch { velocity: Vector3<i32>, replace: [bool; 3] },
+    SetBlocks { changes: Arc<[BlockChange]> },
+    ResendLevel,
+    UpdateBlockAccess
 }
 
 impl Drop for Player {
@@ -197,17 +531,25 @@ impl Player {
         let player = Player {
             world: Arc::default(),
             id: Arc::new(AtomicI8::new(-1)),
+            name_id: Arc::new(AtomicI16::new(-1)),
             handle: tx,
             block_handle: btx,
             username: Arc::default(),
             location: Arc::new(Location {
-                position: Vector3 { x: 0.into(), y: 0.into(), z: 0.into() },
+                position: Vector3 { x: x32::ZERO, y: x32::ZERO, z: x32::ZERO },
                 yaw: 0,
                 pitch: 0,
             }.into()),
             connected: Arc::new(AtomicBool::new(true)),
             uuid: Uuid::new_v4(),
-            supported_exts: Arc::default()
+            supported_exts: Arc::default(),
+            block_support_level: Arc::default(),
+            operator: Arc::default(),
+            rank_level: Arc::default(),
+            held_block: Arc::default(),
+            ping: Arc::default(),
+            selections: Arc::default(),
+            model: Arc::default()
         };
 
         tokio::spawn(player.downgrade().start_loops(rx, brx, server, writer));
@@ -267,6 +609,11 @@ impl WeakPlayer {
                         name
                     };
 
+                    let name_id = gb!(&self.name_id).swap(-1, Ordering::Relaxed);
+                    if name_id >= 0 {
+                        server.remove_from_tab_list(name_id).await;
+                    }
+
                     if let Some(username) = username {
                         let message = format!("&4[&c-&4] &f{username}");
                         tokio::spawn(async move { server.send_message(message).await; });
@@ -277,13 +624,15 @@ impl WeakPlayer {
                 }
                 Command::Initialize { username } => {
                     
-                    let (name, motd, operator, max_players): (String, String, bool, usize);
+                    let (name, motd, operator, rank_level, max_players): (String, String, bool, u8, usize);
                     #[allow(clippy::assigning_clones)] // Doesn't work with non-mutable variables
                     let ban_reason = {
                         let lock = server.config.lock();
                         name = lock.name.clone();
                         motd = lock.motd.clone();
-                        operator = lock.operators.contains(&username);
+                        let rank = lock.rank_of(&username);
+                        operator = rank.has(ranks::OPERATOR);
+                        rank_level = rank.level;
                         max_players = lock.max_players;
                         lock.banned_users.get(&username).map(|reason| format!("Banned: {reason}"))
                     };
@@ -320,6 +669,8 @@ impl WeakPlayer {
                     }
 
                     gb!(&self.username).get_or_init(|| username.clone());
+                    gb!(&self.operator).store(operator, Ordering::Relaxed);
+                    gb!(&self.rank_level).store(rank_level, Ordering::Relaxed);
 
                     let message = format!("&2[&a+&2] &f{username}");
 
@@ -332,6 +683,25 @@ impl WeakPlayer {
                         lock.insert(username, self.clone());
                     }
 
+                    if let Some(name_id) = server.available_name_ids.lock().pop() {
+                        gb!(&self.name_id).store(name_id, Ordering::Relaxed);
+                    }
+
+                    if self.extensions().contains(Extension::ExtPlayerList) {
+                        for entry in server.tab_list().await {
+                            let _ = packet_send.send(entry.into()).await;
+                        }
+                    }
+
+                    if self.extensions().contains(Extension::CustomParticles) {
+                        let packets: Vec<_> = server.effects.lock().iter()
+                            .map(|(&id, effect)| effect.packet(id))
+                            .collect();
+                        for packet in packets {
+                            let _ = packet_send.send(packet).await;
+                        }
+                    }
+
                     server.send_message(message).await;
 
                     self.send_to(default_world.clone()).await;
@@ -339,20 +709,95 @@ impl WeakPlayer {
                 Command::SendTo { world: dst_world } => {
                     let Some(src_world) = self.world.upgrade() else { continue };
                     let Some(id) = self.id.upgrade() else { continue };
+                    let username = gb!(&self.username).get().cloned().unwrap_or_default();
+                    // Everyone can visit the default world, since players join it first
+                    if !Arc::ptr_eq(&dst_world.data, &server.default_world.data)
+                        && !dst_world.data.lock().await.permissions.can_visit(&username, self.rank_level())
                     {
+                        self.send_message("&4[&c!&4] &fYou can't visit that world").await;
+                        continue;
+                    }
+                    let src_world = {
                         let lock = src_world.lock();
                         lock.remove_player(id.load(Ordering::Relaxed));
+                        lock.clone()
+                    };
+                    // Selections don't carry over between worlds
+                    let selections = std::mem::take(&mut *gb!(&self.selections).lock());
+                    if self.extensions().contains(Extension::SelectionCuboid) {
+                        for id in selections.into_keys() {
+                            let _ = packet_send.send(Outgoing::RemoveSelection { id }).await;
+                        }
+                    }
+                    // Neither do hotkeys
+                    if self.extensions().contains(Extension::TextHotKey) {
+                        let hotkeys = src_world.data.lock().await.hotkeys.clone();
+                        for hotkey in hotkeys {
+                            let _ = packet_send.send(hotkey.remove_packet()).await;
+                        }
+                    }
+                    // Nor does the inventory order
+                    if self.extensions().contains(Extension::InventoryOrder)
+                        && !src_world.data.lock().await.palette.is_empty()
+                    {
+                        for packet in blocks::inventory_order(&[]) {
+                            let _ = packet_send.send(packet).await;
+                        }
+                    }
+                    let players: Vec<_> = src_world.players.lock().values().cloned().collect();
+                    for player in players {
+                        player.send_bottom_right(1, format!("&4[&c-&4] &f{username} left the world")).await;
+                    }
+                    // Clients without HackControl read the hack policy from the MOTD, which must be sent before the level
+                    if !self.extensions().contains(Extension::HackControl) {
+                        let hacks = dst_world.data.lock().await.hacks;
+                        let _ = packet_send.send(self.hacks_packet(&server, hacks)).await;
                     }
                     dst_world.add_player(self.clone(), packet_send.clone()).await;
+                    // The player's own location isn't updated until this command is handled
+                    let position = dst_world.data.lock().await.spawn_point.position;
+                    dst_world.spawn_effect(effects::TELEPORT_EFFECT, position, position);
+                    server.update_tab_list(&self).await;
+                    let players: Vec<_> = dst_world.players.lock().values()
+                        .filter(|player| player.uuid != self.uuid)
+                        .cloned()
+                        .collect();
+                    for player in players {
+                        player.send_bottom_right(1, format!("&2[&a+&2] &f{username} joined the world")).await;
+                    }
+                    let name = dst_world.data.lock().await.name.clone();
+                    self.send_status(1, format!("&eWorld: &f{name}")).await;
                 }
-                Command::SetBlock { position: location, id } => {
+                Command::SetBlock { position: location, id, fallback } => {
+                    let id = if self.extensions().contains(Extension::BlockDefinitions) { id } else { fallback };
                     let _ = packet_send.send(
                         Outgoing::SetBlock {
                             position: location,
-                            block: id
+                            block: blocks::fallback(id, self.block_support_level())
                         }
                     ).await;
                 }
+                Command::SetBlocks { changes } => {
+                    let definitions = self.extensions().contains(Extension::BlockDefinitions);
+                    let support_level = self.block_support_level();
+                    let block = |change: &BlockChange| {
+                        blocks::fallback(if definitions { change.id } else { change.fallback }, support_level)
+                    };
+                    if self.extensions().contains(Extension::BulkBlockUpdate) {
+                        for chunk in changes.chunks(256) {
+                            let changes = chunk.iter().map(|change| (change.index, block(change))).collect();
+                            let _ = packet_send.send(Outgoing::BulkBlockUpdate { changes }).await;
+                        }
+                    } else {
+                        for change in changes.iter() {
+                            let _ = packet_send.send(Outgoing::SetBlock { position: change.position, block: block(change) }).await;
+                        }
+                    }
+                }
+                Command::ResendLevel => {
+                    let world = gb!(&self.world).lock().clone();
+                    world.resend_level(&self, &packet_send).await;
+                }
                 Command::SetLocation { location } => {
                     gb!(&self.location).update(location);
                     let arc = gb!(&self.world);
@@ -366,34 +811,102 @@ impl WeakPlayer {
                 }
                 Command::NotifyMove { id, location } => {
                     // TODO: Maybe should use update position?
+                    let extended = self.extensions().contains(Extension::ExtEntityPositions);
                     let _ = packet_send.send(
-                        Outgoing::TeleportPlayer { id, location }
+                        Outgoing::TeleportPlayer { id, location, extended }
                     ).await;
                 }
-                Command::NotifyJoin { mut id, location, name } => {
+                Command::NotifyJoin { mut id, location, name, model } => {
                     if id == gb!(&self.id).load(Ordering::Relaxed) {
                         id = -1;
                     }
-                    let _ = packet_send.send(
+                    let extended = self.extensions().contains(Extension::ExtEntityPositions);
+                    let packet = if self.extensions().contains(Extension::ExtPlayerList) {
+                        Outgoing::ExtAddEntity2 {
+                            id, location, skin: name.clone(), name, extended
+                        }
+                    } else {
                         Outgoing::SpawnPlayer {
-                            id, location, name
+                            id, location, name, extended
                         }
-                    ).await;
+                    };
+                    let _ = packet_send.send(packet).await;
+                    if model != Model::default() {
+                        for packet in model.packets(id, self.extensions()) {
+                            let _ = packet_send.send(packet).await;
+                        }
+                    }
                 }
-                Command::Message { mut message } => {
-                    let supported_exts = gb!(&self.supported_exts)
-                        .get()
-                        .copied()
-                        .unwrap_or(SupportedExtensions::empty());
-                        
+                Command::UpdateHacks => {
+                    let world = gb!(&self.world).lock().clone();
+                    let hacks = world.data.lock().await.hacks;
+                    let _ = packet_send.send(self.hacks_packet(&server, hacks)).await;
+                }
+                Command::UpdateReach => {
+                    if !self.extensions().contains(Extension::ClickDistance) { continue }
+                    let world = gb!(&self.world).lock().clone();
+                    let distance = world.data.lock().await.reach.unwrap_or(DEFAULT_REACH);
+                    let _ = packet_send.send(Outgoing::SetClickDistance { distance }).await;
+                }
+                Command::NotifyHotKey { hotkey, bound } => {
+                    if !self.extensions().contains(Extension::TextHotKey) { continue }
+                    let packet = if bound { hotkey.packet() } else { hotkey.remove_packet() };
+                    let _ = packet_send.send(packet).await;
+                }
+                Command::SpawnEffect { id, position, origin } => {
+                    if !self.extensions().contains(Extension::CustomParticles) { continue }
+                    let _ = packet_send.send(Outgoing::SpawnEffect { id, position, origin }).await;
+                }
+                Command::UpdateSpawnpoint => {
+                    if !self.extensions().contains(Extension::SetSpawnpoint) { continue }
+                    let world = gb!(&self.world).lock().clone();
+                    let location = world.data.lock().await.spawn_point;
+                    let extended = self.extensions().contains(Extension::ExtEntityPositions);
+                    let _ = packet_send.send(Outgoing::SetSpawnpoint { location, extended }).await;
+                }
+                Command::Launch { velocity, replace } => {
+                    if !self.extensions().contains(Extension::VelocityControl) { continue }
+                    let _ = packet_send.send(Outgoing::VelocityControl { velocity, replace }).await;
+                }
+                Command::UpdateBlockAccess => {
+                    if !self.extensions().contains(Extension::BlockPermissions) { continue }
+                    let world = gb!(&self.world).lock().clone();
+                    let username = gb!(&self.username).get().cloned().unwrap_or_default();
+                    let packets = world.data.lock().await
+                        .block_permission_packets(&username, self.rank_level(), self.max_block());
+                    for packet in packets {
+                        let _ = packet_send.send(packet).await;
+                    }
+                }
+                Command::UpdatePalette => {
+                    if !self.extensions().contains(Extension::InventoryOrder) { continue }
+                    let world = gb!(&self.world).lock().clone();
+                    let palette = world.data.lock().await.palette.clone();
+                    for packet in blocks::inventory_order(&palette) {
+                        let _ = packet_send.send(packet).await;
+                    }
+                }
+                Command::NotifyModel { mut id, model } => {
+                    if id == gb!(&self.id).load(Ordering::Relaxed) {
+                        id = -1;
+                    }
+                    for packet in model.packets(id, self.extensions()) {
+                        let _ = packet_send.send(packet).await;
+                    }
+                }
+                Command::Message { mut message, kind } => {
+                    let supported_exts = self.extensions();
+                    let message_types = supported_exts.contains(Extension::MessageTypes);
+                    let Some(kind) = (if message_types { Some(kind) } else { kind.fallback() }) else { continue };
+
                     loop {
                         let Some(idx) = (
-                            if supported_exts.contains(SupportedExtensions::FULL_CP437) {
+                            if supported_exts.contains(Extension::FullCp437) {
                                 let Err(Cp437Error { representable_up_to: idx}) =
                                     message.to_cp437(&CP437_WINGDINGS)
                                     else {break};
                                 Some(idx)
-                            } else if supported_exts.contains(SupportedExtensions::EMOTE_FIX) {
+                            } else if supported_exts.contains(Extension::EmoteFix) {
                                 message.find(|c: char| !c.is_ascii())
                             } else {
                                 message.find(|c: char| !c.is_ascii() && c.is_control())
@@ -412,13 +925,17 @@ impl WeakPlayer {
                         let Some(chunk) = iter.next() else { break };
                         buf = [b' '; 64];
                         buf[..chunk.len()].copy_from_slice(chunk);
-                        let longer_supported = supported_exts.contains(SupportedExtensions::LONGER_MESSAGES);
+                        let longer_supported = supported_exts.contains(Extension::LongerMessages);
+                        let id = if message_types {
+                            kind.id()
+                        } else {
+                            i8::from(longer_supported && iter.peek().is_some())
+                        };
                         if packet_send.send(
-                            Outgoing::Message {
-                                id: i8::from(longer_supported && iter.peek().is_some()),
-                                message: buf
-                            }
+                            Outgoing::Message { id, message: buf }
                         ).await.is_err() { break }
+                        // Only chat messages can span multiple lines
+                        if kind != MessageType::Chat { break }
                     }
                 }
                 Command::NotifyExtensions => {
@@ -426,10 +943,106 @@ impl WeakPlayer {
                         Outgoing::ExtInfoEntry
                     ).await;
                 }
-                Command::SetOperator { operator } => {
+                Command::NotifyBlockSupportLevel => {
+                    let _ = packet_send.send(
+                        Outgoing::CustomBlockSupportLevel { support_level: blocks::CUSTOM_BLOCK_SUPPORT_LEVEL }
+                    ).await;
+                }
+                Command::SetRank { level, operator } => {
+                    gb!(&self.operator).store(operator, Ordering::Relaxed);
+                    gb!(&self.rank_level).store(level, Ordering::Relaxed);
                     let _ = packet_send.send(
                         Outgoing::UpdateUser { operator }
                     ).await;
+                    let world = gb!(&self.world).lock().clone();
+                    let username = gb!(&self.username).get().cloned().unwrap_or_default();
+                    let (packets, hacks) = {
+                        let lock = world.data.lock().await;
+                        (lock.block_permission_packets(&username, level, self.max_block()), lock.hacks)
+                    };
+                    if self.extensions().contains(Extension::BlockPermissions) {
+                        for packet in packets {
+                            let _ = packet_send.send(packet).await;
+                        }
+                    }
+                    // Operators may be able to bypass the world's hack policy
+                    let _ = packet_send.send(self.hacks_packet(&server, hacks)).await;
+                }
+                Command::NotifyBlockDefinition { id, definition } => {
+                    let packet = match definition {
+                        Some(definition) => definition.packet(id, self.extensions()),
+                        None => self.extensions()
+                            .contains(Extension::BlockDefinitions)
+                            .then_some(Outgoing::RemoveBlockDefinition { id })
+                    };
+                    if let Some(packet) = packet {
+                        let _ = packet_send.send(packet).await;
+                    }
+                }
+                Command::NotifyListAdd { entry } => {
+                    if self.extensions().contains(Extension::ExtPlayerList) {
+                        let _ = packet_send.send(entry.into()).await;
+                    }
+                }
+                Command::NotifyListRemove { name_id } => {
+                    if self.extensions().contains(Extension::ExtPlayerList) {
+                        let _ = packet_send.send(
+                            Outgoing::ExtRemovePlayerName { name_id }
+                        ).await;
+                    }
+                }
+                Command::HoldBlock { block, prevent_change } => {
+                    if self.extensions().contains(Extension::HeldBlock) {
+                        let _ = packet_send.send(
+                            Outgoing::HoldThis { block, prevent_change }
+                        ).await;
+                    }
+                }
+                Command::SetHotbar { block, index } => {
+                    if self.extensions().contains(Extension::SetHotbar) {
+                        let _ = packet_send.send(
+                            Outgoing::SetHotbar { block, index }
+                        ).await;
+                    }
+                }
+                Command::MakeSelection { id, selection } => {
+                    gb!(&self.selections).lock().insert(id, selection.clone());
+                    if self.extensions().contains(Extension::SelectionCuboid) {
+                        let _ = packet_send.send(
+                            Outgoing::MakeSelection { id, selection }
+                        ).await;
+                    } else {
+                        let (label, start, end) = (&selection.label, selection.start(), selection.end());
+                        let size = selection.size();
+                        self.send_message(format!(
+                            "&3[&b#&3] &fSelected {label} from {}, {}, {} to {}, {}, {} ({}x{}x{})",
+                            start.x, start.y, start.z, end.x, end.y, end.z, size.x, size.y, size.z
+                        )).await;
+                    }
+                }
+                Command::RemoveSelection { id } => {
+                    let removed = gb!(&self.selections).lock().remove(&id).is_some();
+                    if removed && self.extensions().contains(Extension::SelectionCuboid) {
+                        let _ = packet_send.send(
+                            Outgoing::RemoveSelection { id }
+                        ).await;
+                    }
+                }
+                Command::Pong { data } => {
+                    let _ = packet_send.send(
+                        Outgoing::TwoWayPing { server_to_client: false, data }
+                    ).await;
+                }
+                Command::UpdateEnvironment => {
+                    let Some(world) = self.world.upgrade() else { continue };
+                    let world = world.lock().clone();
+                    let packets = {
+                        let lock = world.data.lock().await;
+                        lock.environment.packets(lock.level_data.dimensions, self.extensions())
+                    };
+                    for packet in packets {
+                        let _ = packet_send.send(packet).await;
+                    }
                 }
             }
         }
@@ -438,6 +1051,13 @@ impl WeakPlayer {
     /// Start the loop for sending packets to the client.
     async fn start_packets(self, mut recv: Receiver<Outgoing>, mut writer: OwnedWriteHalf, timeout: Duration) {
         while let Some(packet) = recv.recv().await {
+            // Extension packets could confuse or disconnect clients that didn't negotiate them
+            if let Some(extension) = Extension::for_packet(packet.id()) {
+                if !self.extensions().contains(extension) {
+                    debug!("Dropped packet {packet:?} to {}, which didn't negotiate {}", self.uuid, extension.info().name);
+                    continue;
+                }
+            }
             let Ok(()) = time::timeout(timeout, packet.store(&mut writer))
                 .await
                 .map_err(|_| io::Error::from(ErrorKind::TimedOut))
@@ -451,10 +1071,43 @@ impl WeakPlayer {
 
     /// Start the loop for placing blocks on the server.
     async fn start_block_queue(self, mut brx: Receiver<(Vector3<u16>, u8)>) {
+        let mut last_rejection: Option<(BlockRejection, Instant)> = None;
         'o: while let Some((location, id)) = brx.recv().await {
             if !gb!(&self.connected).load(Ordering::Relaxed) {
                 break;
             }
+            let world = g!(&self.world; break).lock().clone();
+            let origin = Location::from(&*gb!(&self.location)).position;
+            let username = gb!(&self.username).get().cloned().unwrap_or_default();
+            let level = self.rank_level();
+            let rejection = {
+                let lock = world.data.lock().await;
+                let result = if lock.permissions.can_build(&username, level) {
+                    lock.check_block_change(location, id, origin, level, self.max_block())
+                } else {
+                    Err(BlockRejection::CantBuild)
+                };
+                result.map_err(|rejection| {
+                    let original = lock.level_data.get(location).unwrap_or_default();
+                    let fallback = lock.block_definitions.get(&original).map_or(original, |definition| definition.fallback);
+                    (rejection, original, fallback)
+                })
+            };
+            if let Err((rejection, original, fallback)) = rejection {
+                debug!("Rejected block change from {}: {rejection}", self.uuid);
+                // Put the original block back, since the client has already changed it
+                if rejection != BlockRejection::OutOfBounds {
+                    self.set_block(original, location, fallback).await;
+                }
+                let repeated = last_rejection.is_some_and(|(last, sent)|
+                    last == rejection && sent.elapsed() < REJECTION_MESSAGE_SPACING
+                );
+                if !repeated {
+                    self.send_message(format!("&4[&c!&4] &f{rejection}")).await;
+                    last_rejection = Some((rejection, Instant::now()));
+                }
+                continue;
+            }
             while {
                 let arc = g!(&self.world; break 'o);
                 let lock = arc.lock();
@@ -471,7 +1124,7 @@ impl WeakPlayer {
         let mut interval = time::interval(spacing);
 
         while self.connected.upgrade().is_some_and(|c| c.load(Ordering::Relaxed)) &&
-            time::timeout(timeout, send.send(Outgoing::Ping))
+            time::timeout(timeout, send.send(self.heartbeat_packet(timeout)))
             .await
             .map_err(|_| ())
             .map(|v| v.map_err(|_| ()))
@@ -485,6 +1138,54 @@ impl WeakPlayer {
         let () = self.notify_disconnect("Timed out").await;
     }
 
+    /// Gets the packet to send for a heartbeat, starting a new ping if the player supports `TwoWayPing`.
+    fn heartbeat_packet(&self, timeout: Duration) -> Outgoing {
+        if !self.extensions().contains(Extension::TwoWayPing) {
+            return Outgoing::Ping;
+        }
+        let Some(ping) = self.ping.upgrade() else { return Outgoing::Ping };
+        let data = ping.lock().start(timeout);
+        Outgoing::TwoWayPing { server_to_client: true, data }
+    }
+
+    /// Gets the player's latency as the minimum, average, and maximum of their recent round-trip times,
+    /// or `None` if it hasn't been measured.
+    pub fn latency(&self) -> Option<(Duration, Duration, Duration)> {
+        let ping = self.ping.upgrade()?;
+        let lock = ping.lock();
+        Some((lock.min()?, lock.average()?, lock.max()?))
+    }
+
+    /// Gets the player's latency as shown in the player list, or `None` if it hasn't been measured.
+    pub fn listed_latency(&self) -> Option<Duration> {
+        self.ping.upgrade()?.lock().listed()
+    }
+
+    /// Reads a client's `ExtInfo` and `ExtEntry` packets, and negotiates the extensions that both sides support.
+    /// Other packets sent before every `ExtEntry` has arrived are ignored.
+    async fn negotiate_extensions(&self, stream: &mut OwnedReadHalf) -> Result<SupportedExtensions, ProtocolError> {
+        let count = match Incoming::load(&mut *stream).await? {
+            Incoming::ExtInfo { count } => count,
+            packet => return Err(ProtocolError::UnexpectedPacket { expected: "ExtInfo", got: packet.id() })
+        };
+        debug!("Client supports {count} extensions");
+        let mut supported_exts = SupportedExtensions::empty();
+        let mut received = 0;
+        while received < count {
+            match Incoming::load(&mut *stream).await? {
+                Incoming::ExtEntry { name, version } => {
+                    received += 1;
+                    debug!("Client supports extension \"{name}\" with version {version}");
+                    if let Some(version) = supported_exts.negotiate(&name, version) {
+                        debug!("Negotiated extension \"{name}\" with version {version}");
+                    }
+                }
+                packet => debug!("Ignoring {packet:?} from {} while negotiating extensions", self.uuid)
+            }
+        }
+        Ok(supported_exts)
+    }
+
     /// Handle the packets for a player. This will block.
     #[allow(clippy::too_many_lines)]
     pub async fn handle_packets(self, mut stream: OwnedReadHalf, server: RunningServer) {
@@ -498,10 +1199,10 @@ impl WeakPlayer {
         while self.connected.upgrade().is_some_and(|b| b.load(Ordering::Relaxed)) {
 
             // Using a match instead of .map_err since I need to break
-            let res = match Incoming::load(&mut stream).await {
+            let res = match Incoming::load_with(&mut stream, self.extensions()).await {
                 Ok(v) => v,
                 Err(err) => {
-                    let () = self.notify_disconnect(format!("Connection died: {err}")).await;
+                    let () = self.notify_disconnect(err.to_string()).await;
                     break;
                 }
             };
@@ -539,20 +1240,35 @@ impl WeakPlayer {
                     
                     if cpe_supported {
                         self.send_ext_info().await;
-                        let supported_exts = match Incoming::load(&mut stream).await {
-                            Ok(Incoming::ExtInfoEntry {supported_exts}) => supported_exts,
-                            Ok(_) => {
-                                let () = self.notify_disconnect("Client replied inappropriately to ExtInfo packet".to_string()).await;
-                                break;
-                            }
+                        let supported_exts = match self.negotiate_extensions(&mut stream).await {
+                            Ok(supported_exts) => supported_exts,
                             Err(err) => {
-                                let () = self.notify_disconnect(format!("Connection died: {err}")).await;
+                                let () = self.notify_disconnect(err.to_string()).await;
                                 break;
                             }
                         };
                         
                         let Some(once) = self.supported_exts.upgrade() else { break };
                         once.get_or_init(|| supported_exts);
+
+                        if supported_exts.contains(Extension::CustomBlocks) {
+                            self.send_block_support_level().await;
+                            let support_level = match Incoming::load_with(&mut stream, supported_exts).await {
+                                Ok(Incoming::CustomBlockSupportLevel { support_level }) => support_level,
+                                Ok(packet) => {
+                                    let err = ProtocolError::UnexpectedPacket { expected: "CustomBlockSupportLevel", got: packet.id() };
+                                    let () = self.notify_disconnect(err.to_string()).await;
+                                    break;
+                                }
+                                Err(err) => {
+                                    let () = self.notify_disconnect(err.to_string()).await;
+                                    break;
+                                }
+                            };
+
+                            let Some(once) = self.block_support_level.upgrade() else { break };
+                            once.get_or_init(|| support_level.min(blocks::CUSTOM_BLOCK_SUPPORT_LEVEL));
+                        }
                     }
 
                     let Ok(()) = gb!(&self.handle).send(Command::Initialize {
@@ -564,7 +1280,10 @@ impl WeakPlayer {
                     let Ok(()) = gb!(&self.block_handle).send((position, state)).await else { break };
                 }
 
-                Incoming::SetLocation { location } => {
+                Incoming::SetLocation { location, held_block } => {
+                    if self.extensions().contains(Extension::HeldBlock) {
+                        gb!(&self.held_block).store(held_block, Ordering::Relaxed);
+                    }
                     let Ok(()) = gb!(&self.handle).send(
                         Command::SetLocation { location }
                     ).await else { break };
@@ -586,19 +1305,9 @@ impl WeakPlayer {
                     }
 
                     if !append {
-                        let operator = {
-                            let conf = server.config.lock();
-                            let username_arc = self.username.upgrade();
-                            username_arc.is_some_and(
-                                |name| name.get().is_some_and(
-                                    |name| conf.operators.contains(name)
-                                )
-                            )
-                        };
-
                         // Process commands
                         if message_buffer.starts_with('/') {
-                            match self.execute_command(message_buffer, server.clone()).await {
+                            match commands::execute(CommandSender::Player(self.clone()), &server, &message_buffer[1..]).await {
                                 Ok(b) => if b { break },
                                 Err(error_message) => {
                                     self.send_message(format!("&4[&c!&4] &f{error_message}")).await;
@@ -609,306 +1318,161 @@ impl WeakPlayer {
                         }
 
                         let username = gb!(&self.username).get().cloned().unwrap_or_default();
-                        let message = if operator {
-                            format!("&3[&b{username}&3] &f{message_buffer}")
-                        } else {
-                            format!("&8[&7{username}&8] &f{message_buffer}")
-                        };
+                        let color = server.config.lock().rank_of(&username).color.clone();
+                        let message = format!("&8[{color}{username}&8] &f{message_buffer}");
                         server.send_message(message).await;
                         message_buffer = String::new();
                     }
                 },
-                Incoming::ExtInfoEntry { .. } => {
+                Incoming::ExtInfo { .. } => {
                     self.notify_disconnect("Got ExtInfo at unexpected time").await;
                     break;
                 }
-            }
-        }
-    }
-
-    /// Executes a single command.
-    #[allow(clippy::too_many_lines)]
-    async fn execute_command(&self, raw_message: String, server: RunningServer) -> Result<bool, String> {
-
-        let operator = {
-            let conf = server.config.lock();
-            let username_arc = self.username.upgrade()
-                .ok_or("Username dropped".to_string())?;
-            let Some(username) = username_arc.get()
-                else { return Err("Username not initialized".to_string())};
-            conf.operators.contains(username)
-        };
-
-        let mut arguments = raw_message[1..].split_ascii_whitespace();
-        let name = arguments.next().unwrap_or("");
-        match name {
-            "world" => match arguments.next() {
-                Some("join") => {
-                    // FIXME: This requires nightly.
-                    let Some(world_name) = arguments.remainder() else {
-                        return Err("No world name specified".into())
-                    };
-                    let lock = server.worlds.lock().await;
-                    let Some(world) = lock.get(world_name).cloned() else {
-                        return Err(format!("World \"{world_name}\" doesn't exist"))
-                    };
-                    self.send_to(world).await;
-                },
-                Some("list") => {
-                    self.send_message("&6[&eWorld List&6]").await;
-                    let worlds = {
-                        let lock = server.worlds.lock().await;
-                        lock.keys().cloned().collect_vec()
+                Incoming::ExtEntry { name, .. } => {
+                    debug!("Ignoring late ExtEntry for \"{name}\" from {}", self.uuid);
+                }
+                Incoming::CustomBlockSupportLevel { .. } => {
+                    self.notify_disconnect("Got CustomBlockSupportLevel at unexpected time").await;
+                    break;
+                }
+                Incoming::PlayerClick { button, pressed, yaw, pitch, target_entity, target_block } => {
+                    let event = ClickEvent {
+                        player: self.clone(),
+                        button,
+                        pressed,
+                        yaw,
+                        pitch,
+                        target_entity,
+                        target_block
                     };
-                    for world in worlds {
-                        self.send_message(format!("- {world}")).await;
+                    for handler in server.click_handlers.lock().iter() {
+                        handler.on_click(&event, &server);
                     }
-                },
-                Some("spawnpoint") if operator => {
-                    let Some(world) = self.world.upgrade() else { return Ok(false) };
-                    let data = world.lock().data.clone();
-                    let Some(location) = self.location.upgrade().map(|v| (&*v).into())
-                        else { return Ok(false) };
-                    data.lock().await.spawn_point = location;
-                    self.send_message("&3[&b#&3] &fSet world spawn to current location").await;
-                },
-                Some("rename") if operator => {
-                    let Some(world_name) = arguments.remainder() else {
-                        return Err("No world name specified".into())
-                    };
-                    let Some(world) = self.world.upgrade() else { return Ok(false) };
-                    let world_lock = world.lock().clone();
-                    let mut data = world_lock.data.lock().await;
-                    let old_name = data.name.clone();
-                    server.worlds.lock().await.remove(&old_name);
-                    data.name = world_name.to_string();
-                    self.send_message(format!("&3[&b#&3] &fRenamed world to \"{world_name}\".")).await;
-                }
-                Some("save") if operator => {
-                    let Some(world) = self.world.upgrade() else { return Ok(false) };
-                    let data = world.lock().data.clone();
-                    let name = data.lock().await.name.clone();
-                    server.send_message(format!("&6[&e@&6] &fSaving world \"{name}\"...")).await;
-                    let world = world.lock().clone();
-                    let new_file = world.filepath.get().is_some();
-                    if let Err(err) = world.clone().save().await {
-                        server.send_message("&4[&c!&4] Failed to save! See logs for details.").await;
-                        warn!("Failed to save world \"{name}\": {err}");
-                        return Ok(false);
+                }
+                Incoming::TwoWayPing { server_to_client: false, data } => {
+                    self.pong(data).await;
+                }
+                Incoming::TwoWayPing { server_to_client: true, data } => {
+                    let updated = {
+                        let ping = gb!(&self.ping);
+                        let mut lock = ping.lock();
+                        lock.finish(data) && lock.update_listed()
                     };
-                    info!("Saved world \"{name}\"");
-                    server.send_message("&6[&e@&6] &fWorld saved!".to_string()).await;
-                    if new_file {
-                        self.send_message(format!(
-                            "&2[&b#&2] &fSaved to \"{}\".",
-                            world.filepath.get()
-                                .and_then(|f| f.file_name())
-                                .expect(".save() should initialize the filepath")
-                                .to_string_lossy()
-                        )).await;
+                    // Only update the player list when the shown ping moves noticeably
+                    if updated {
+                        server.update_tab_list(&self).await;
                     }
-                },
-                Some("create") if operator => {
-                    let Some(length) = arguments.next() else { return Err("No length specified".into()) };
-                    let Some(width) = arguments.next() else { return Err("No width specified".into()) };
-                    let Some(height) = arguments.next() else { return Err("No height specified".into()) };
-                    let Some(generator) = arguments.next() else { return Err("No generator specified, see /world generators".into()) };
-                    let seed: u64 = match arguments.next() {
-                        Some(seed) => seed.parse().unwrap_or_else(
-                            |_| fxhash::hash64(seed.as_bytes())
-                        ),
-                        None => rand::random()
-                    };
-                    
-                    let length: u16 = length.parse().map_err(|err| format!("Invalid length: {err}"))?;
-                    let width: u16 = width.parse().map_err(|err| format!("Invalid width: {err}"))?;
-                    let height: u16 = height.parse().map_err(|err| format!("Invalid height: {err}"))?;
-                    let dimensions = Vector3 { x: length, z: width, y: height };
-
-                    let world;
-                    {
-                        let lock = server.generators.lock();
-                        let Some(generator) = lock.get(generator).map(|v| &**v) else { return Err(format!("Invalid generator {generator}"))};
-                        let data = generator.generate(dimensions, seed)
-                            .map_err(|err| format!("Generation error: {err}"))?;
-                        world = World::from_data(WorldData {
-                            level_data: LevelData {
-                                raw_data: data,
-                                dimensions
-                            },
-                            spawn_point: Location {
-                                position: Vector3 {
-                                    x: x16::from_num(length.min(2047)) / 2,
-                                    y: x16::from_num(height.min(2047)),
-                                    z: x16::from_num(width.min(2047)) / 2
-                                },
-                                yaw: 0,
-                                pitch: 0
-                            },
-                            name: format!("<tmp-{}>", Uuid::new_v4()),
-                        }, None);
-                    }
-                    self.send_to(world).await;
-                    self.send_message("&3[&b#&3] &fWorld generated!").await;
-                    self.send_message("&3[&b#&3] &fBe sure to &b/world rename&f and &b/world save&f.").await;
-                }
-                Some("generators") if operator => {
-                    self.send_message("&6[&eWorld Generators&6]").await;
-                    let keys: Vec<_> = {
-                        let lock = server.generators.lock();
-                        lock.keys().cloned().collect()
-                    };
-                    for gen in keys {
-                        self.send_message(format!("- {gen}")).await;
-                    }
-                }
-                Some(cmd) => return Err(format!("Invalid subcommand \"{cmd}\". See /help")),
-                None => return Err("No subcommand. See /help".to_string()),
-            },
-            "stop" if operator => {
-                server.stop().await;
-                return Ok(true);
-            },
-            "w" => {
-                let Some(name) = arguments.next() else {
-                    return Err("No username specified".into())
-                };
-                let players = server.connected_players.lock().await;
-                let Some(player) = players.get(name).cloned() else {
-                    return Err(format!("User {name} is not online"))
-                };
-                let Some(own_name) = self.username.upgrade().and_then(|v| v.get().cloned()) else {
-                    return Err(String::new()) // They won't see this anyways
-                };
-                let Some(message) = arguments.remainder() else {
-                    return Err("Message must be non-empty".into())
-                };
-                self.send_message(format!("&0[&8{own_name} -> {name}&0] &7{message}")).await;
-                player.send_message(format!("&0[&8{own_name} -> {name}&0] &7{message}")).await;
-            }
-            "locate" => {
-                if let Some(name) = arguments.next() {
-                    let players = server.connected_players.lock().await;
-                    let Some(world) = players.get(name).cloned().and_then(|player| player.world.upgrade()) else {
-                        return Err(format!("User {name} is not online"))
-                    };
-                    let world = world.lock().clone();
-                    self.send_message(format!("{name} is in \"{}\"", world.data.lock().await.name)).await;
-                } else {
-                    let Some(world) = self.world.upgrade() else { return Ok(false) };
-                    let world = world.lock().clone();
-                    self.send_message(format!("You are in \"{}\"", world.data.lock().await.name)).await;
-                };
-            }
-            "players" => {
-                let players = server.connected_players.lock().await;
-                self.send_message("&3[&bPlayer List&3]").await;
-                for name in players.keys() {
-                    self.send_message(name.to_string()).await;
                 }
             }
-            "op" if operator => {
-                let Some(name) = arguments.next() else {
-                    return Err("No username specified".into())
-                };
-                let players = server.connected_players.lock().await;
-
-                {
-                    let mut conf = server.config.lock();
-                    conf.operators.insert(name.to_string());
-                }
-
-                if let Some(player) = players.get(name) {
-                    player.send_message("&3[&b#&3] &fGranted operator permissions".to_string()).await;
-                    player.notify_operator(true).await;
-                };
-
-                self.send_message(format!("&3[&b#&3] &fGranted operator permissions to {name}")).await;
-            },
-            "deop" if operator => {
-                let Some(name) = arguments.next() else {
-                    return Err("No username specified".into())
-                };
-                let players = server.connected_players.lock().await;
+        }
+    }
 
-                {
-                    let mut conf = server.config.lock();
-                    conf.operators.remove(name);
-                }
+    /// Gets the position of the block the player is standing in, clamped to the bounds of their world.
+    pub async fn block_position(&self) -> Result<Vector3<u16>, String> {
+        let Some(location) = self.location.upgrade() else { return Err(String::new()) };
+        let Some(world) = self.world.upgrade() else { return Err(String::new()) };
+        let world = world.lock().clone();
+        let dimensions = world.data.lock().await.level_data.dimensions;
+        let position = Location::from(&*location).block_position();
+        let clamp = |coordinate: i32, size: u16| {
+            u16::try_from(coordinate.clamp(0, i32::from(size) - 1)).unwrap_or_default()
+        };
+        Ok(Vector3 {
+            x: clamp(position.x, dimensions.x),
+            y: clamp(position.y, dimensions.y),
+            z: clamp(position.z, dimensions.z)
+        })
+    }
 
-                if let Some(player) = players.get(name) {
-                    player.send_message("&3[&b#&3] &fOperator permissions revoked".to_string()).await;
-                    player.notify_operator(false).await;
-                };
+    /// Changes the player's model, notifying everyone in their world.
+    pub async fn update_model(&self, update: impl FnOnce(&mut Model)) {
+        let Some(model) = self.model.upgrade() else { return };
+        let model = {
+            let mut lock = model.lock();
+            update(&mut lock);
+            lock.clone()
+        };
+        let Some(id) = self.id.upgrade().map(|id| id.load(Ordering::Relaxed)) else { return };
+        let Some(world) = self.world.upgrade() else { return };
+        let world = world.lock().clone();
+        let players: Vec<_> = world.players.lock().values().cloned().collect();
+        for player in players {
+            player.notify_model(id, model.clone()).await;
+        }
+    }
 
-                self.send_message(format!("&3[&b#&3] &fRevoked operator permissions from {name}")).await;
+    /// Updates a custom block definition in the player's world, notifying everyone in it.
+    /// If the definition is `None`, then the block's definition is removed.
+    ///
+    /// Players whose clients don't support `BlockDefinitions` are sent the level again if it has the block,
+    /// since they were shown its old fallback.
+    pub async fn update_block_definition(&self, id: u8, definition: Option<BlockDefinition>) {
+        let Some(world) = self.world.upgrade() else { return };
+        let world = world.lock().clone();
+        let placed = {
+            let mut lock = world.data.lock().await;
+            if let Some(definition) = definition.clone() {
+                lock.block_definitions.insert(id, definition);
+            } else {
+                lock.block_definitions.remove(&id);
             }
-            "kick" if operator => {
-                let Some(name) = arguments.next() else {
-                    return Err("No username specified".into())
-                };
-                let players = server.connected_players.lock().await;
-                let Some(player) = players.get(name) else {
-                    return Err("Player is offline".into())
-                };
-                player.notify_disconnect(
-                    format!("Kicked: {}", arguments.remainder().unwrap_or("No reason given"))
-                ).await;
-
-                self.send_message(format!("&3[&b#&3] &fKicked {name}")).await;
-            },
-            "ban" if operator => {
-                let Some(name) = arguments.next() else {
-                    return Err("No username specified".into())
-                };
-                let players = server.connected_players.lock().await;
-                let reason = arguments.remainder().unwrap_or("No reason given");
-                if let Some(player) = players.get(name) {
-                    player.notify_disconnect(
-                        format!("Banned: {reason}")
-                    ).await;
-                };
+            lock.level_data.raw_data.contains(&id)
+        };
+        let players: Vec<_> = world.players.lock().values().cloned().collect();
+        for player in players {
+            // Sending the level also sends its block permissions and palette
+            if placed && !player.extensions().contains(Extension::BlockDefinitions) {
+                player.resend_level().await;
+                continue;
+            }
+            player.notify_block_definition(id, definition.clone()).await;
+            player.notify_block_access().await;
+            player.notify_palette().await;
+        }
+    }
+}
 
-                self.send_message(format!("&3[&b#&3] &fBanned {name}")).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How long pings are kept waiting for a reply in these tests.
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn ping_slower_than_spacing() {
+        let mut ping = PingStats::default();
+        let first = ping.start(TIMEOUT);
+        // More heartbeats are sent before the first one is answered
+        let second = ping.start(TIMEOUT);
+        ping.start(TIMEOUT);
+        assert!(ping.finish(first));
+        assert!(ping.finish(second));
+        assert!(!ping.finish(first));
+        assert_eq!(ping.samples.len(), 2);
+        assert_eq!(ping.pending.len(), 1);
+    }
 
-                let mut config = server.config.lock();
-                config.banned_users.insert(name.to_string(), reason.to_string());
-            },
-            "unban" if operator => {
-                let Some(name) = arguments.next() else {
-                    return Err("No username specified".into())
-                };
+    #[test]
+    fn ping_expires() {
+        let mut ping = PingStats::default();
+        let stale = ping.start(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(5));
+        ping.start(Duration::from_millis(1));
+        assert!(!ping.finish(stale));
+        assert_eq!(ping.pending.len(), 1);
+    }
 
-                self.send_message(format!("&3[&b#&3] &fUnbanned {name}")).await;
-
-                let mut config = server.config.lock();
-                config.banned_users.remove(name);
-            },
-            "help" => {
-                self.send_message("&5[&dCommand List&5]").await;
-                self.send_message("- /world").await;
-                self.send_message("  - /world join <name>").await;
-                self.send_message("  - /world list").await;
-                if operator {
-                    self.send_message("&b  - /world save").await;
-                    self.send_message("&b  - /world generators").await;
-                    self.send_message("&b  - /world spawnpoint").await;
-                    self.send_message("&b  - /world create <length> <width> <height> <generator> [seed]").await;
-                }
-                self.send_message("- /w <user> <message>").await;
-                self.send_message("- /locate [user=self]").await;
-                self.send_message("- /players").await;
-                if operator {
-                    self.send_message("&b- /op <name>").await;
-                    self.send_message("&b- /deop <name>").await;
-                    self.send_message("&b- /kick <name> [reason]").await;
-                    self.send_message("&b- /ban <name> [reason]").await;
-                    self.send_message("&b- /unban <name>").await;
-                    self.send_message("&b- /stop").await;
-                }
-            }
-            _ => return Err(format!("Invalid command {name}"))
-        }
-        Ok(false)
+    #[test]
+    fn listed_ping_steps() {
+        let mut ping = PingStats::default();
+        assert!(!ping.update_listed());
+        ping.samples.push_back(Duration::from_millis(100));
+        assert!(ping.update_listed());
+        ping.samples[0] = Duration::from_millis(105);
+        assert!(!ping.update_listed());
+        ping.samples[0] = Duration::from_millis(111);
+        assert!(ping.update_listed());
+        assert_eq!(ping.listed(), Some(Duration::from_millis(111)));
     }
 }
@@ -0,0 +1,98 @@
+//! Holds the ranks that players can have, and the permissions that ranks can grant.
+
+use std::collections::BTreeSet;
+
+/// Counts as an operator in worlds, bypassing hack policies.
+/// This also lets the player's client delete bedrock.
+pub const OPERATOR: &str = "operator";
+/// Lets a player create, rename, and save worlds.
+pub const WORLD_MANAGE: &str = "world.manage";
+/// Lets a player change the settings and permissions of any world.
+pub const WORLD_EDIT: &str = "world.edit";
+/// Lets a player define, edit, and remove custom blocks.
+pub const BLOCK_DEFINE: &str = "block.define";
+/// Lets a player fill their selection with a block.
+pub const FILL: &str = "build.fill";
+/// Lets a player change other players' models and scales.
+pub const PLAYER_MODEL: &str = "player.model";
+/// Lets a player launch other players.
+pub const PLAYER_LAUNCH: &str = "player.launch";
+/// Lets a player kick other players.
+pub const KICK: &str = "player.kick";
+/// Lets a player ban and unban other players.
+pub const BAN: &str = "player.ban";
+/// Lets a player change the ranks of other players, up to their own rank.
+pub const SET_RANK: &str = "player.rank";
+/// Lets a player make announcements.
+pub const ANNOUNCE: &str = "server.announce";
+/// Lets a player stop the server.
+pub const STOP: &str = "server.stop";
+
+/// The level of the default moderator rank.
+/// Blocks that only operators could use by default need at least this level,
+/// and worlds saved before ranks had levels use it for their operator-only rules.
+pub const OPERATOR_LEVEL: u8 = 50;
+
+/// A rank that players can have, configured in the server's config.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Rank {
+    /// The rank's name, as used in commands.
+    pub name: String,
+    /// The rank's level. Higher ranks have higher levels.
+    pub level: u8,
+    /// The color code that the names of players with the rank are shown in, such as `&b`.
+    pub color: String,
+    /// The permission nodes granted by the rank.
+    ///
+    /// `*` grants every permission, and a node ending in `.*` grants every permission starting with it.
+    pub permissions: BTreeSet<String>,
+}
+
+impl Rank {
+    /// Checks if the rank grants a permission.
+    #[must_use]
+    pub fn has(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|node| {
+            node == "*" || node == permission || node.strip_suffix('*').is_some_and(|prefix| {
+                prefix.ends_with('.') && permission.starts_with(prefix)
+            })
+        })
+    }
+
+    /// Gets the rank's name in its color.
+    #[must_use]
+    pub fn display_name(&self) -> String {
+        format!("{}{}", self.color, self.name)
+    }
+}
+
+/// Creates the ranks that new servers are configured with, from lowest to highest.
+#[must_use]
+pub fn default_ranks() -> Vec<Rank> {
+    let rank = |name: &str, level, color: &str, permissions: &[&str]| Rank {
+        name: name.into(),
+        level,
+        color: color.into(),
+        permissions: permissions.iter().map(|&node| node.into()).collect(),
+    };
+    vec![
+        rank("guest", 0, "&7", &[]),
+        rank("builder", 10, "&a", &[]),
+        rank("moderator", OPERATOR_LEVEL, "&e", &[
+            OPERATOR, WORLD_EDIT, FILL, PLAYER_MODEL, PLAYER_LAUNCH, KICK, BAN, ANNOUNCE
+        ]),
+        rank("admin", 100, "&b", &["*"]),
+    ]
+}
+
+/// Creates the rank that commands run from the server's console have, which grants every permission.
+#[must_use]
+pub fn console() -> Rank {
+    Rank {
+        name: "console".into(),
+        level: u8::MAX,
+        color: "&4".into(),
+        permissions: BTreeSet::from(["*".into()]),
+    }
+}
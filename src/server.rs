@@ -4,7 +4,9 @@
 // TODO: Refactor this to not be one giant file
 
 use crate::{
-    packets::{Outgoing, OutgoingPacketType}, player::{Player, WeakPlayer}, structs::Config, world::World, worldgen::WorldGenerator
+    packets::{MessageType, Outgoing, OutgoingPacketType}, player::{Player, WeakPlayer}, structs::Config, world::World, worldgen::WorldGenerator,
+    events::{ClickHandler, PlayerInfo},
+    effects::{self, ParticleEffect}
 };
 use rand::{
     rngs::StdRng,
@@ -12,13 +14,15 @@ use rand::{
     Rng
 };
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     io::ErrorKind,
     net::Ipv4Addr,
     sync::Arc,
     time::Instant,
-    sync::OnceLock
+    sync::OnceLock,
+    sync::atomic::Ordering
 };
+use arrayvec::ArrayVec;
 use futures::future::join_all;
 use tokio::{
     io::{self, AsyncWriteExt},
@@ -49,9 +53,11 @@ impl SaltExt for StdRng {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ServerCommand {
     Stop,
-    SendChatMessage {
+    SendMessage {
         /// The message to be sent.
         message: String,
+        /// Where the message is shown.
+        kind: MessageType,
     },
 }
 
@@ -95,17 +101,65 @@ pub struct RunningServer {
     /// The server's URL.
     pub url: Arc<OnceLock<String>>,
     /// A map of names to world generators.
-    pub generators: Arc<Mutex<HashMap<String, Box<dyn WorldGenerator>>>>
+    pub generators: Arc<Mutex<HashMap<String, Box<dyn WorldGenerator>>>>,
+    /// A list of available IDs for names in the player list.
+    pub available_name_ids: Arc<Mutex<ArrayVec<i16, 256>>>,
+    /// A list of handlers that are called whenever a player clicks.
+    pub click_handlers: Arc<Mutex<Vec<Box<dyn ClickHandler>>>>,
+    /// The particle effects defined for every player, by ID.
+    pub effects: Arc<Mutex<BTreeMap<u8, ParticleEffect>>>
+}
+
+/// A single player's entry in the player list.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TabListEntry {
+    /// The server-wide ID of the entry.
+    pub name_id: i16,
+    /// The player's username.
+    pub name: String,
+    /// The name shown in the player list.
+    pub list_name: String,
+    /// The group the player is listed under. This is the name of the world they're in.
+    pub group: String,
+    /// The player's rank within their group. Lower ranks are listed first.
+    pub rank: u8,
+}
+
+impl From<TabListEntry> for Outgoing {
+    fn from(entry: TabListEntry) -> Self {
+        Outgoing::ExtAddPlayerName {
+            name_id: entry.name_id,
+            player_name: entry.name,
+            list_name: entry.list_name,
+            group_name: entry.group,
+            group_rank: entry.rank,
+        }
+    }
 }
 
 impl RunningServer {
     command_wrapper! {
-        /// Sends a message in chat for all players.
-        pub async fn send_message(&self, message: String) => SendChatMessage;
+        /// Sends a message of the given type to all players.
+        pub async fn send_typed_message(&self, message: String, kind: MessageType) => SendMessage;
         /// Stops the server.
         pub async fn stop(&self) => Stop;
     }
 
+    /// Sends a message in chat for all players.
+    pub async fn send_message(&self, message: impl Into<String>) {
+        self.send_typed_message(message, MessageType::Chat).await;
+    }
+
+    /// Sets one of the status lines of all players, from 1 to 3.
+    pub async fn send_status(&self, line: u8, message: impl Into<String>) {
+        self.send_typed_message(message, MessageType::Status(line)).await;
+    }
+
+    /// Shows an announcement in the center of every player's screen.
+    pub async fn send_announcement(&self, message: impl Into<String>) {
+        self.send_typed_message(message, MessageType::Announcement).await;
+    }
+
     pub fn collect_garbage(&self) {
         let Ok(mut lock) = self.connected_players.try_lock() else { return; };
         lock.retain(|_, player| !player.any_dropped());
@@ -128,27 +182,91 @@ impl RunningServer {
                 ("default".into(), Box::new(Superflat {
                     layers: vec![(0x7, 1), (0x1, 6), (0x3, 2), (0x2, 1)],
                 }) as Box<dyn WorldGenerator>)
-            ])))
+            ]))),
+            available_name_ids: Arc::new(Mutex::new(
+                (0..256).rev().collect()
+            )),
+            click_handlers: Arc::new(Mutex::new(vec![
+                Box::new(PlayerInfo) as Box<dyn ClickHandler>
+            ])),
+            effects: Arc::new(Mutex::new(effects::default_effects()))
+        })
+    }
+
+    /// Gets a player's entry in the player list, or `None` if they aren't fully connected.
+    async fn tab_list_entry(&self, player: &WeakPlayer) -> Option<TabListEntry> {
+        let name_id = player.name_id.upgrade()?.load(Ordering::Relaxed);
+        if name_id < 0 {
+            return None;
+        }
+        let name = player.username.upgrade()?.get().cloned()?;
+        let world = player.world.upgrade()?.lock().clone();
+        let group = world.data.lock().await.name.clone();
+        let rank = self.config.lock().rank_of(&name).clone();
+        let color = &rank.color;
+        let list_name = match player.listed_latency() {
+            Some(latency) => format!("{color}{name} &8{}ms", latency.as_millis()),
+            None => format!("{color}{name}"),
+        };
+        Some(TabListEntry {
+            name_id,
+            list_name,
+            name,
+            group,
+            // Lower ranks are sorted first in the player list
+            rank: u8::MAX - rank.level,
         })
     }
 
+    /// Gets the player list entries of every connected player.
+    pub async fn tab_list(&self) -> Vec<TabListEntry> {
+        let players: Vec<_> = self.connected_players.lock().await.values().cloned().collect();
+        let mut entries = Vec::with_capacity(players.len());
+        for player in players {
+            if let Some(entry) = self.tab_list_entry(&player).await {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    /// Adds or updates a player's entry in every connected player's player list.
+    pub async fn update_tab_list(&self, player: &WeakPlayer) {
+        let Some(entry) = self.tab_list_entry(player).await else { return };
+        let players: Vec<_> = self.connected_players.lock().await.values().cloned().collect();
+        for other in players {
+            other.notify_list_add(entry.clone()).await;
+        }
+    }
+
+    /// Removes an entry from every connected player's player list, freeing up its ID.
+    pub async fn remove_from_tab_list(&self, name_id: i16) {
+        self.available_name_ids.lock().push(name_id);
+        let players: Vec<_> = self.connected_players.lock().await.values().cloned().collect();
+        for player in players {
+            player.notify_list_remove(name_id).await;
+        }
+    }
+
     async fn start_commands(self, mut rx: mpsc::Receiver<ServerCommand>, stop_condvar: Arc<Condvar>) {
         while let Some(command) = rx.recv().await {
             match command {
-                ServerCommand::SendChatMessage {
-                    message
+                ServerCommand::SendMessage {
+                    message, kind
                 } => {
                     let lock = self.connected_players.lock().await;
                     // If left with an & prefix, vanilla clients will crash
                     let message = message.strip_suffix('&').unwrap_or(&message);
                     
-                    info!("[CHAT] {message}");
+                    if kind == MessageType::Chat {
+                        info!("[CHAT] {message}");
+                    }
 
                     #[allow(clippy::unnecessary_to_owned)] // False positive
                     for player in lock.values().cloned() {
                         let message = message.to_owned();
                         tokio::spawn(async move {
-                            player.send_message(message).await;
+                            player.send_typed_message(message, kind).await;
                         });
                     }
                 }
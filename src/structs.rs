@@ -5,6 +5,7 @@ use std::net::{IpAddr, Ipv4Addr};
 use std::path::PathBuf;
 use std::time::Duration;
 use serde::Serialize;
+use crate::ranks::{self, Rank};
 
 mod duration_float {
     use std::fmt::Formatter;
@@ -58,8 +59,19 @@ pub struct Config {
     pub banned_ips: HashMap<IpAddr, String>,
     /// A mapping of banned usernames to their ban reasons.
     pub banned_users: HashMap<String, String>,
-    /// A set of usernames that are operators.
+    /// A set of usernames that are operators, from before ranks were added.
+    ///
+    /// These are moved to the highest rank when the config is loaded.
+    #[serde(default, skip_serializing)]
     pub operators: HashSet<String>,
+    /// The ranks that players can have.
+    #[serde(default = "ranks::default_ranks")]
+    pub ranks: Vec<Rank>,
+    /// A mapping of usernames to the names of their ranks.
+    ///
+    /// Players that aren't listed have the lowest rank.
+    #[serde(default)]
+    pub player_ranks: HashMap<String, String>,
     /// The amount of salts to keep for verifying users.
     ///
     /// If this is zero, then users will not be verified.
@@ -121,17 +133,18 @@ impl Default for Config {
             max_players: 64,
             public: false,
             operators: HashSet::new(),
+            ranks: ranks::default_ranks(),
+            player_ranks: HashMap::new(),
             motd: "Running on Honeybit".into(),
             max_message_length: 256
         }
     }
 }
 
-static COMMENT_MAP: [(&str, &str); 17] = [
+static COMMENT_MAP: [(&str, &str); 18] = [
     ("packet_timeout", "How long the server should wait before disconnecting a player, in seconds."),
     ("ping_spacing", "How often the server sends pings to clients, in seconds."),
     ("default_world", "The world that players first connect to when joining."),
-    ("operators", "A list of usernames that have operator permissions."),
     ("kept_salts", "How many \"salts\" to keep in memory.\nSalts are used to verify a user's key.\nIf this is set to 0, then users will not be verified."),
     ("name", "The server's displayed name."),
     ("heartbeat_url", "The URL to ping for heartbeat pings.\n\nIf this is left blank, then no heartbeat pings will be sent.\nIf this is left blank AND kept_salts is above 0,\nthe program will exit with an error,\nas it will be impossible for users to join."),
@@ -145,9 +158,50 @@ static COMMENT_MAP: [(&str, &str); 17] = [
     ("max_message_length", "The maximum length of a sent message. Messages above this threshold will be clipped."),
     ("[banned_ips]", "A mapping of IPs to ban reasons."),
     ("[banned_users]", "A mapping of usernames to ban reasons."),
+    ("[player_ranks]", "A mapping of usernames to rank names.\nPlayers that aren't listed have the lowest rank."),
+    ("[[ranks]]", "A rank that players can have.\nThe permission \"*\" grants every permission."),
 ];
 
 impl Config {
+    /// Updates a config loaded from an older version of the server,
+    /// moving operators to the highest rank and sorting the ranks from lowest to highest.
+    pub fn migrate(&mut self) {
+        if self.ranks.is_empty() {
+            warn!("No ranks are configured, using the default ranks");
+            self.ranks = ranks::default_ranks();
+        }
+        self.ranks.sort_by_key(|rank| rank.level);
+        if !self.operators.is_empty() {
+            let highest = self.highest_rank().name.clone();
+            info!("Moving {} operators to rank \"{highest}\"", self.operators.len());
+            for operator in self.operators.drain() {
+                self.player_ranks.insert(operator, highest.clone());
+            }
+        }
+    }
+
+    /// Finds a rank by name.
+    pub fn rank(&self, name: &str) -> Option<&Rank> {
+        self.ranks.iter().find(|rank| rank.name == name)
+    }
+
+    /// Gets the lowest rank, which players have by default.
+    pub fn lowest_rank(&self) -> &Rank {
+        self.ranks.first().expect("there should always be at least one rank")
+    }
+
+    /// Gets the highest rank.
+    pub fn highest_rank(&self) -> &Rank {
+        self.ranks.last().expect("there should always be at least one rank")
+    }
+
+    /// Gets the rank of a player.
+    pub fn rank_of(&self, username: &str) -> &Rank {
+        self.player_ranks.get(username)
+            .and_then(|name| self.rank(name))
+            .unwrap_or_else(|| self.lowest_rank())
+    }
+
     pub fn save(&self, buf: &mut String) -> io::Result<()> {
         self.serialize(toml::Serializer::pretty(buf))
             .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
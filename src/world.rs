@@ -1,22 +1,25 @@
 //! Holds structs pertaining to a world in a server.
 #![allow(clippy::unnecessary_to_owned)] // There are many false positives in this file.
 
-use std::{fs, io, path::PathBuf, sync::{Arc, atomic::Ordering}};
+use std::{fmt, fs, io, path::PathBuf, sync::{Arc, atomic::Ordering}};
 use std::fs::File;
 use std::io::{Cursor, Read, Write};
 use std::sync::OnceLock;
 use arrayvec::ArrayVec;
 use flate2::Compression;
-use flate2::read::GzEncoder;
+use flate2::read::{DeflateEncoder, GzEncoder};
 use mint::Vector3;
-use crate::{packets::Location, player::WeakPlayer, WORLD_PATH};
+use crate::{packets::Location, player::WeakPlayer, blocks, effects, ranks, WORLD_PATH};
 use identity_hash::IntMap;
 use itertools::Itertools;
 use tokio::sync::Mutex as TokioMutex;
 use parking_lot::Mutex;
 use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
-use crate::packets::Outgoing;
+use std::collections::{BTreeMap, BTreeSet};
+use crate::blocks::BlockDefinition;
+use crate::packets::{Outgoing, x32};
+use crate::extensions::{Extension, SupportedExtensions};
 
 
 /// A single world within a server.
@@ -33,7 +36,7 @@ pub struct World {
 }
 
 /// A holding class for a serialized level .DAT file.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 #[allow(clippy::module_name_repetitions)]
 pub struct WorldData {
     /// The raw level data.
@@ -42,6 +45,385 @@ pub struct WorldData {
     pub spawn_point: Location,
     /// The world's name.
     pub name: String,
+    /// A mapping of block IDs to their custom definitions.
+    pub block_definitions: BTreeMap<u8, BlockDefinition>,
+    /// The world's environment settings.
+    pub environment: Environment,
+    /// Which hacks players can use in the world.
+    pub hacks: HackPolicy,
+    /// How far away players can place and break blocks, in 32nds of a block.
+    /// If this is `None`, then the default reach of 5 blocks is used.
+    pub reach: Option<i16>,
+    /// The hotkeys given to players in the world.
+    pub hotkeys: Vec<HotKey>,
+    /// The blocks shown in players' inventories, in order.
+    /// If this is empty, then the client's default inventory is used.
+    pub palette: Vec<u8>,
+    /// Who can place and delete each block in the world.
+    /// Blocks that aren't listed use [`RankRequirement::default_for_block`].
+    pub block_access: BTreeMap<u8, RankRequirement>,
+    /// Who owns the world, and who can visit and build in it.
+    pub permissions: WorldPermissions,
+}
+
+/// The lowest rank needed to do something in a world, like visiting it or placing a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RankRequirement {
+    /// Players whose rank has at least this level.
+    Level(u8),
+    /// Nobody, whatever their rank. The world's owner and builders can still visit and build in it.
+    Nobody,
+}
+
+impl Default for RankRequirement {
+    fn default() -> Self {
+        RankRequirement::Level(0)
+    }
+}
+
+impl RankRequirement {
+    /// Gets who can place and delete a block in worlds that don't override it.
+    #[must_use]
+    pub fn default_for_block(id: u8) -> Self {
+        if blocks::OPERATOR_BLOCKS.contains(&id) {
+            RankRequirement::Level(ranks::OPERATOR_LEVEL)
+        } else {
+            RankRequirement::Level(0)
+        }
+    }
+
+    /// Checks if a player meets this requirement, by the level of their rank.
+    #[must_use]
+    pub fn allows(self, level: u8) -> bool {
+        match self {
+            RankRequirement::Level(minimum) => level >= minimum,
+            RankRequirement::Nobody => false,
+        }
+    }
+
+    /// Converts a requirement from before ranks had levels, where 0 was everyone, 1 was operators, and 2 was nobody.
+    #[must_use]
+    pub fn from_legacy(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(RankRequirement::Level(0)),
+            1 => Some(RankRequirement::Level(ranks::OPERATOR_LEVEL)),
+            2 => Some(RankRequirement::Nobody),
+            _ => None
+        }
+    }
+}
+
+impl From<RankRequirement> for [u8; 2] {
+    fn from(requirement: RankRequirement) -> Self {
+        match requirement {
+            RankRequirement::Level(level) => [0, level],
+            RankRequirement::Nobody => [1, 0],
+        }
+    }
+}
+
+impl TryFrom<[u8; 2]> for RankRequirement {
+    type Error = u8;
+
+    fn try_from([kind, level]: [u8; 2]) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(RankRequirement::Level(level)),
+            1 => Ok(RankRequirement::Nobody),
+            _ => Err(kind)
+        }
+    }
+}
+
+/// Who owns a world, and who can visit and build in it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WorldPermissions {
+    /// The username of the world's owner, who can edit its permissions.
+    pub owner: Option<String>,
+    /// The usernames of the players who can always visit and build in the world.
+    pub builders: BTreeSet<String>,
+    /// The lowest rank that can visit the world.
+    /// Everyone can visit the server's default world, since players join it first.
+    pub visit: RankRequirement,
+    /// The lowest rank that can build in the world.
+    pub build: RankRequirement,
+}
+
+impl WorldPermissions {
+    /// Checks if a player is the world's owner or one of its builders.
+    #[must_use]
+    pub fn is_builder(&self, username: &str) -> bool {
+        self.owner.as_deref() == Some(username) || self.builders.contains(username)
+    }
+
+    /// Checks if a player can visit the world, by their username and the level of their rank.
+    #[must_use]
+    pub fn can_visit(&self, username: &str, level: u8) -> bool {
+        self.is_builder(username) || self.visit.allows(level)
+    }
+
+    /// Checks if a player can build in the world, by their username and the level of their rank.
+    #[must_use]
+    pub fn can_build(&self, username: &str, level: u8) -> bool {
+        self.is_builder(username) || self.build.allows(level)
+    }
+
+    /// Checks if a player can edit the world's permissions.
+    #[must_use]
+    pub fn can_edit(&self, username: &str, operator: bool) -> bool {
+        operator || self.owner.as_deref() == Some(username)
+    }
+}
+
+/// The default reach of a player, in 32nds of a block.
+pub const DEFAULT_REACH: i16 = 160;
+
+/// How far past the world's reach a block change can be before it's rejected, in 32nds of a block.
+/// This allows for the distance between a player's position and the edges of a block, and for movement lag.
+pub const REACH_LEEWAY: i16 = 64;
+
+/// Why a player's block change was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockRejection {
+    /// The block is outside of the level.
+    OutOfBounds,
+    /// The block ID isn't supported by the player's client, or isn't defined in the world.
+    InvalidBlock(u8),
+    /// The block is further away than the world's reach.
+    OutOfReach,
+    /// The player can't place the block.
+    CantPlace(u8),
+    /// The player can't delete the block.
+    CantDelete(u8),
+    /// The player can't build in the world at all.
+    CantBuild,
+}
+
+impl fmt::Display for BlockRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockRejection::OutOfBounds => write!(f, "That block is outside of the world"),
+            BlockRejection::InvalidBlock(id) => write!(f, "Block {id} doesn't exist"),
+            BlockRejection::OutOfReach => write!(f, "That block is too far away"),
+            BlockRejection::CantPlace(id) => write!(f, "You can't place block {id} in this world"),
+            BlockRejection::CantDelete(id) => write!(f, "You can't delete block {id} in this world"),
+            BlockRejection::CantBuild => write!(f, "You can't build in this world"),
+        }
+    }
+}
+
+/// A key bound to send some text, given to clients that support `TextHotKey`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HotKey {
+    /// The text sent when the key is pressed.
+    /// If this ends in `◙`, then the text is sent immediately instead of being put in the chat box.
+    pub action: String,
+    /// The LWJGL key code of the key.
+    pub key_code: i32,
+    /// A bitfield of the modifier keys to hold. 1 is Ctrl, 2 is Shift, and 4 is Alt.
+    pub modifiers: u8,
+}
+
+impl HotKey {
+    /// Gets the packet that binds this hotkey.
+    #[must_use]
+    pub fn packet(&self) -> Outgoing {
+        Outgoing::SetTextHotKey {
+            label: self.action.trim_end_matches('◙').to_string(),
+            action: self.action.clone(),
+            key_code: self.key_code,
+            modifiers: self.modifiers,
+        }
+    }
+
+    /// Gets the packet that unbinds this hotkey.
+    #[must_use]
+    pub fn remove_packet(&self) -> Outgoing {
+        Outgoing::SetTextHotKey {
+            label: String::new(),
+            action: String::new(),
+            key_code: self.key_code,
+            modifiers: self.modifiers,
+        }
+    }
+}
+
+/// Which hacks players are allowed to use in a world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct HackPolicy {
+    /// Whether players can fly.
+    pub flying: bool,
+    /// Whether players can walk through blocks.
+    pub noclip: bool,
+    /// Whether players can move faster.
+    pub speeding: bool,
+    /// Whether players can respawn and set their spawn point.
+    pub respawn: bool,
+    /// Whether players can use third person view.
+    pub third_person: bool,
+    /// How high players can jump, in 32nds of a block. If this is `None`, then the client's default is used.
+    pub jump_height: Option<i16>,
+    /// Whether operators can use every hack regardless of the rest of the policy.
+    pub operator_bypass: bool,
+}
+
+impl Default for HackPolicy {
+    fn default() -> Self {
+        Self {
+            flying: true,
+            noclip: true,
+            speeding: true,
+            respawn: true,
+            third_person: true,
+            jump_height: None,
+            operator_bypass: true,
+        }
+    }
+}
+
+impl HackPolicy {
+    /// Gets the packet that applies the policy to a player that supports `HackControl`.
+    #[must_use]
+    pub fn packet(self, operator: bool) -> Outgoing {
+        let bypass = operator && self.operator_bypass;
+        Outgoing::HackControl {
+            flying: self.flying || bypass,
+            noclip: self.noclip || bypass,
+            speeding: self.speeding || bypass,
+            respawn: self.respawn || bypass,
+            third_person: self.third_person || bypass,
+            jump_height: self.jump_height.unwrap_or(-1),
+        }
+    }
+
+    /// Gets the flags to add to the MOTD to apply the policy to players that don't support `HackControl`.
+    #[must_use]
+    pub fn motd_flags(self) -> String {
+        let disallowed = [
+            (self.flying, "-fly"),
+            (self.noclip, "-noclip"),
+            (self.speeding, "-speed"),
+            (self.respawn, "-respawn"),
+            (self.third_person, "-thirdperson"),
+        ].into_iter().filter(|(allowed, _)| !allowed).map(|(_, flag)| flag).collect_vec();
+
+        let mut flags = if disallowed.len() == 5 {
+            vec!["-hax".to_string()]
+        } else {
+            disallowed.into_iter().map(str::to_string).collect_vec()
+        };
+        if !flags.is_empty() && self.operator_bypass {
+            flags.push("+ophax".into());
+        }
+        if let Some(height) = self.jump_height {
+            flags.push(format!("jumpheight={}", f32::from(height) / 32.0));
+        }
+        flags.join(" ")
+    }
+}
+
+/// A cuboid region of a world, shown to clients that support `SelectionCuboid`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Selection {
+    /// The name of the selection.
+    pub label: String,
+    /// The first corner that was picked, which can be any corner of the selection.
+    pub first: Vector3<u16>,
+    /// The second corner that was picked, opposite the first. This is inclusive.
+    pub second: Vector3<u16>,
+    /// The color of the selection.
+    pub color: [u8; 3],
+    /// The opacity of the selection, from 0 to 255.
+    pub opacity: u8,
+}
+
+impl Selection {
+    /// Creates a new selection between two opposite corners, in any order.
+    #[must_use]
+    pub fn new(label: String, first: Vector3<u16>, second: Vector3<u16>) -> Self {
+        Self {
+            label,
+            first,
+            second,
+            color: [0x40, 0x80, 0xff],
+            opacity: 0x60,
+        }
+    }
+
+    /// Gets the corner of the selection closest to the origin.
+    #[must_use]
+    pub fn start(&self) -> Vector3<u16> {
+        let (a, b) = (self.first, self.second);
+        Vector3 { x: a.x.min(b.x), y: a.y.min(b.y), z: a.z.min(b.z) }
+    }
+
+    /// Gets the corner of the selection furthest from the origin. This is inclusive.
+    #[must_use]
+    pub fn end(&self) -> Vector3<u16> {
+        let (a, b) = (self.first, self.second);
+        Vector3 { x: a.x.max(b.x), y: a.y.max(b.y), z: a.z.max(b.z) }
+    }
+
+    /// Gets the dimensions of the selection in blocks.
+    #[must_use]
+    pub fn size(&self) -> Vector3<u32> {
+        let (start, end) = (self.start(), self.end());
+        Vector3 {
+            x: u32::from(end.x - start.x) + 1,
+            y: u32::from(end.y - start.y) + 1,
+            z: u32::from(end.z - start.z) + 1,
+        }
+    }
+}
+
+/// The environment settings of a world, such as its colors and weather.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Environment {
+    /// The world's colors, in the order of [`Environment::COLOR_NAMES`].
+    /// If a color is `None`, then the client's default is used.
+    pub colors: [Option<[u8; 3]>; 5],
+    /// The block shown on the sides of the map. If this is `None`, then bedrock is used.
+    pub side_block: Option<u8>,
+    /// The block shown around the edges of the map. If this is `None`, then water is used.
+    pub edge_block: Option<u8>,
+    /// The height of the edge block around the map. If this is `None`, then half the map's height is used.
+    pub water_level: Option<i32>,
+    /// The world's weather. 0 is sunny, 1 is raining, and 2 is snowing.
+    pub weather: u8,
+}
+
+impl Environment {
+    /// The names of each of the world's colors.
+    pub const COLOR_NAMES: [&'static str; 5] = ["sky", "cloud", "fog", "ambient", "diffuse"];
+    /// The names of each type of weather.
+    pub const WEATHER_NAMES: [&'static str; 3] = ["sun", "rain", "snow"];
+
+    /// Gets the packets that send the environment to a player with the given extensions.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn packets(&self, dimensions: Vector3<u16>, extensions: SupportedExtensions) -> Vec<Outgoing> {
+        let mut packets = Vec::new();
+        if extensions.contains(Extension::EnvColors) {
+            for (variable, color) in self.colors.iter().enumerate() {
+                packets.push(Outgoing::EnvSetColor { variable: variable as u8, color: *color });
+            }
+        }
+        if extensions.contains(Extension::EnvMapAspect) {
+            packets.push(Outgoing::SetMapEnvProperty {
+                property: 0, value: i32::from(self.side_block.unwrap_or(7))
+            });
+            packets.push(Outgoing::SetMapEnvProperty {
+                property: 1, value: i32::from(self.edge_block.unwrap_or(8))
+            });
+            packets.push(Outgoing::SetMapEnvProperty {
+                property: 2, value: self.water_level.unwrap_or(i32::from(dimensions.y / 2))
+            });
+        }
+        if extensions.contains(Extension::EnvWeatherType) {
+            packets.push(Outgoing::EnvSetWeatherType { weather: self.weather });
+        }
+        packets
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -63,27 +445,129 @@ impl LevelData {
         }
     }
 
-    /// Gets the ID of a block in the level.
+    /// Gets the index of a block in the level data, or `None` if it's out of bounds.
     #[must_use]
-    pub fn get(&self, position: Vector3<u16>) -> Option<u8> {
+    pub fn index(&self, position: Vector3<u16>) -> Option<usize> {
         if position.x >= self.dimensions.x || position.y >= self.dimensions.y || position.z >= self.dimensions.z {
             None
         } else {
             let pos = Vector3 { x: position.x as usize, y: position.y as usize, z: position.z as usize };
             let size = Vector3 { x: self.dimensions.x as usize, y: self.dimensions.y as usize, z: self.dimensions.z as usize };
-            self.raw_data.get(pos.y * size.x * size.z + pos.z * size.x + pos.x).copied()
+            Some(pos.y * size.x * size.z + pos.z * size.x + pos.x)
         }
     }
 
+    /// Gets the ID of a block in the level.
+    #[must_use]
+    pub fn get(&self, position: Vector3<u16>) -> Option<u8> {
+        self.raw_data.get(self.index(position)?).copied()
+    }
+
     /// Gets a mutable reference to the ID of a block in the level.
     pub fn get_mut(&mut self, position: Vector3<u16>) -> Option<&mut u8> {
-        if position.x >= self.dimensions.x || position.y >= self.dimensions.y || position.z >= self.dimensions.z {
-            None
-        } else {
-            let pos = Vector3 { x: position.x as usize, y: position.y as usize, z: position.z as usize };
-            let size = Vector3 { x: self.dimensions.x as usize, y: self.dimensions.y as usize, z: self.dimensions.z as usize };
-            self.raw_data.get_mut(pos.y * size.x * size.z + pos.z * size.x + pos.x)
+        let index = self.index(position)?;
+        self.raw_data.get_mut(index)
+    }
+}
+
+/// The number of blocks that [`World::set_blocks`] can change before players are sent the whole level instead.
+pub const RESEND_THRESHOLD: usize = 8192;
+
+/// A single block changed by [`World::set_blocks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockChange {
+    /// The position of the block.
+    pub position: Vector3<u16>,
+    /// The index of the block in the level data.
+    pub index: i32,
+    /// The block's new ID.
+    pub id: u8,
+    /// The block shown to clients that don't support `BlockDefinitions`.
+    pub fallback: u8,
+}
+
+impl WorldData {
+    /// Gets who can place and delete a block in the world.
+    #[must_use]
+    pub fn block_access(&self, id: u8) -> RankRequirement {
+        self.block_access.get(&id).copied().unwrap_or_else(|| RankRequirement::default_for_block(id))
+    }
+
+    /// Checks whether a player with a rank of some level can place and delete a block in the world.
+    #[must_use]
+    pub fn can_use_block(&self, id: u8, level: u8) -> bool {
+        self.block_access(id).allows(level)
+    }
+
+    /// Checks whether a block ID is supported by a player's client, whose highest supported block is `max_block`,
+    /// and is either built in or defined in the world.
+    ///
+    /// # Errors
+    /// Errors with [`BlockRejection::InvalidBlock`] if the block can't be used.
+    pub fn check_block_id(&self, id: u8, max_block: u8) -> Result<(), BlockRejection> {
+        if id > max_block || (id > blocks::MAX_CUSTOM_BLOCK && !self.block_definitions.contains_key(&id)) {
+            return Err(BlockRejection::InvalidBlock(id));
         }
+        Ok(())
+    }
+
+    /// Checks whether a player can change a block in the world.
+    ///
+    /// `origin` is the player's position, `level` is the level of their rank,
+    /// and `max_block` is the highest block ID their client supports.
+    ///
+    /// # Errors
+    /// Errors with the reason the change was rejected.
+    pub fn check_block_change(
+        &self,
+        position: Vector3<u16>,
+        id: u8,
+        origin: Vector3<x32>,
+        level: u8,
+        max_block: u8,
+    ) -> Result<(), BlockRejection> {
+        let original = self.level_data.get(position).ok_or(BlockRejection::OutOfBounds)?;
+        self.check_block_id(id, max_block)?;
+
+        let reach = i64::from(self.reach.unwrap_or(DEFAULT_REACH).max(0)) + i64::from(REACH_LEEWAY);
+        let distance_squared: i64 = [
+            (position.x, origin.x),
+            (position.y, origin.y),
+            (position.z, origin.z),
+        ].into_iter()
+            .map(|(block, origin)| i64::from(block) * 32 + 16 - i64::from(origin.to_bits()))
+            .map(|offset| offset * offset)
+            .sum();
+        if distance_squared > reach * reach {
+            return Err(BlockRejection::OutOfReach);
+        }
+
+        if id != 0 && !self.can_use_block(id, level) {
+            return Err(BlockRejection::CantPlace(id));
+        }
+        // Clients only place blocks into air and liquids, and building over a liquid doesn't delete it,
+        // so anything else is from a modified client
+        let deletable = match (original, id) {
+            (0, _) | (8..=11, 1..) => true,
+            (_, 0) => self.can_use_block(original, level),
+            _ => false,
+        };
+        if !deletable {
+            return Err(BlockRejection::CantDelete(original));
+        }
+        Ok(())
+    }
+
+    /// Gets the packets that tell a player, by their username and the level of their rank,
+    /// which blocks up to `max_block` they can place and delete in the world.
+    /// Players who can't build in the world can't use any block.
+    #[must_use]
+    pub fn block_permission_packets(&self, username: &str, level: u8, max_block: u8) -> Vec<Outgoing> {
+        let can_build = self.permissions.can_build(username, level);
+        (1..=max_block).map(|block| {
+            let allowed = can_build && self.can_use_block(block, level);
+            Outgoing::SetBlockPermission { block, allow_placement: allowed, allow_deletion: allowed }
+        }).collect()
     }
 }
 
@@ -101,13 +585,7 @@ impl Default for World {
             available_ids: Arc::new(Mutex::new(
                 [0].into_iter().collect()
             )),
-            data: Arc::new(TokioMutex::new(
-                WorldData {
-                    level_data: LevelData::default(),
-                    spawn_point: Location::default(),
-                    name: String::new(),
-                }
-            )),
+            data: Arc::new(TokioMutex::new(WorldData::default())),
         }
     }
 }
@@ -115,15 +593,56 @@ impl Default for World {
 
 struct WorldEncoder<'inner> {
     inner: Cursor<&'inner [u8]>,
+    /// Whether the length prefix has been read, or shouldn't be written at all.
     length_read: bool,
+    /// A lookup table of blocks to replace with others, for clients that don't support every block.
+    fallbacks: Option<Box<[u8; 256]>>,
 }
 
 impl<'inner> WorldEncoder<'inner> {
-    fn new(slice: &'inner [u8]) -> Self {
+    fn new(slice: &'inner [u8], fallbacks: Option<Box<[u8; 256]>>, length_prefix: bool) -> Self {
         Self {
             inner: Cursor::new(slice),
-            length_read: false,
+            length_read: !length_prefix,
+            fallbacks,
+        }
+    }
+
+    /// Gets how much of the level has been read, as a percentage.
+    #[allow(clippy::cast_possible_truncation)]
+    fn percent_complete(&self) -> u8 {
+        let length = self.inner.get_ref().len().max(1) as u64;
+        (self.inner.position() * 100 / length) as u8
+    }
+}
+
+/// Streams compressed level data to a player in chunks as it's being compressed.
+/// This blocks while compressing and waiting for room in the packet queue, so it must be run with `spawn_blocking`.
+/// Returns `None` if the player has disconnected.
+fn send_level_chunks<'inner, R: Read>(
+    mut encoder: R,
+    level: fn(&R) -> &WorldEncoder<'inner>,
+    packet_send: &Sender<Outgoing>
+) -> Option<()> {
+    loop {
+        let mut buf = [0; 1024];
+        let mut length = 0;
+        // A single read may not fill the whole chunk
+        while length < buf.len() {
+            let read = encoder.read(&mut buf[length..]).expect("reading from a slice should never fail");
+            if read == 0 { break; }
+            length += read;
+        }
+        if length == 0 {
+            return Some(());
         }
+
+        #[allow(clippy::cast_possible_truncation)]
+        packet_send.blocking_send(Outgoing::LevelDataChunk {
+            data_length: length as u16,
+            data_chunk: Box::new(buf),
+            percent_complete: level(&encoder).percent_complete(),
+        }).ok()?;
     }
 }
 
@@ -131,7 +650,13 @@ impl<'inner> Read for WorldEncoder<'inner> {
     #[allow(clippy::cast_possible_truncation)]
     fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
         if self.length_read {
-            self.inner.read(buf)
+            let read = self.inner.read(buf)?;
+            if let Some(fallbacks) = &self.fallbacks {
+                for block in &mut buf[..read] {
+                    *block = fallbacks[*block as usize];
+                }
+            }
+            Ok(read)
         } else {
             let len = self.inner.get_ref().len() as u32;
             let slice = len.to_be_bytes();
@@ -142,6 +667,20 @@ impl<'inner> Read for WorldEncoder<'inner> {
     }
 }
 
+/// A copy of everything needed to send a player a level, taken so the world isn't locked while it's sent.
+struct LevelSnapshot {
+    /// The blocks of the level.
+    raw_data: Vec<u8>,
+    /// The size of the level.
+    dimensions: Vector3<u16>,
+    /// The blocks to replace the level's blocks with, if the player's client can't show all of them.
+    fallbacks: Option<Box<[u8; 256]>>,
+    /// Whether the player's client supports `FastMap`.
+    fast_map: bool,
+    /// The packets sent after the level data, like block definitions and the environment.
+    packets: Vec<Outgoing>,
+}
+
 macro_rules! fire_and_forget {
     ($expr: expr) => {
         tokio::spawn(async move {
@@ -167,77 +706,41 @@ impl World {
 
     /// Creates a new player in the world. Returns the new ID, or None if the server is full.
     ///
-    ///
+    /// This must be called from the player's command loop. The player is added to the world as the level is copied,
+    /// so block updates made after the copy are queued as commands and handled once the level has been sent.
     pub async fn add_player(&self, player: WeakPlayer, packet_send: Sender<Outgoing>) -> Option<i8> {
         self.collect_garbage();
 
-        // We hold the lock for the entire time here so that
-        // any block updates aren't pushed until the world data is done being sent
-        let data_lock = self.data.lock().await;
-
-        let dimensions = data_lock.level_data.dimensions;
-        
-        // GZip level data
-        let data_slice = data_lock.level_data.raw_data.as_slice();
-        
-        let expected_area = dimensions.x as usize * dimensions.y as usize * dimensions.z as usize;
-        let actual_area = data_slice.len();
-        if actual_area != expected_area {
-            warn!(
-                "World \"{}\" is corrupt (expected {expected_area} bytes for {}x{}x{}, got {actual_area} bytes)",
-                data_lock.name,
-                dimensions.x,
-                dimensions.y,
-                dimensions.z
-            );
-            player.notify_disconnect(
-                format!("World is corrupt (exp. {expected_area} bytes, got {actual_area} bytes)")
-            ).await;
-            return None;
-        }
-
-        debug!("{} bytes to compress", data_slice.len());
-
-        let Ok(()) = packet_send.send(Outgoing::LevelInit).await else { return None; };
-
-        let mut encoder = GzEncoder::new(WorldEncoder::new(data_slice), Compression::fast());
-
-        // For some reason, streaming the encoded data refused to work.
-        // Really annoying but oh well I guess >:/
-        let mut encoded_data = Vec::new();
-        encoder.read_to_end(&mut encoded_data).expect("reading into Vec should never fail");
-
-        let mut buf = [0; 1024];
-
-        let iter = encoded_data.chunks(1024);
-        let chunk_count = iter.len();
-
-        for (i, chunk) in iter.enumerate() {
-            buf[..chunk.len()].copy_from_slice(chunk);
-
-            #[allow(clippy::pedantic)]
-                let Ok(()) = packet_send.send(Outgoing::LevelDataChunk {
-                data_length: chunk.len() as u16,
-                data_chunk: Box::new(buf),
-                percent_complete: ((i as f32) / (chunk_count as f32) * 100.0) as u8,
-            }).await else { return None; };
-        }
-
-        let Ok(()) = packet_send.send(Outgoing::LevelFinalize { size: dimensions }).await
-            else { return None; };
-
-        drop(data_lock);
-
-        let id = self.available_ids.lock().pop()?;
-
         let player_name = player.username.upgrade()?.get().cloned()?;
 
-        player.id.upgrade()?.store(id, Ordering::Relaxed);
-        {
-            let world = player.world.upgrade()?;
-            if world.is_locked() { return None; }
-            let mut lock = world.lock();
-            *lock = self.clone();
+        let level = {
+            let data_lock = self.data.lock().await;
+            match Self::snapshot_level(&data_lock, &player) {
+                Ok(level) => {
+                    let id = self.available_ids.lock().pop()?;
+                    player.id.upgrade()?.store(id, Ordering::Relaxed);
+                    {
+                        let world = player.world.upgrade()?;
+                        if world.is_locked() { return None; }
+                        let mut lock = world.lock();
+                        *lock = self.clone();
+                    }
+                    self.players.lock().insert(id, player.clone());
+                    level
+                }
+                Err(reason) => {
+                    drop(data_lock);
+                    player.notify_disconnect(reason).await;
+                    return None;
+                }
+            }
+        };
+        Self::send_level(level, &packet_send).await?;
+
+        // Don't let momentum carry over from the last world
+        if player.extensions().contains(Extension::VelocityControl) {
+            let packet = Outgoing::VelocityControl { velocity: Vector3 { x: 0, y: 0, z: 0 }, replace: [true; 3] };
+            let Ok(()) = packet_send.send(packet).await else { return None; };
         }
 
         let default_location = {
@@ -247,14 +750,13 @@ impl World {
 
         debug!("{default_location:?}");
 
-        player.notify_join(-1, default_location, player_name.clone()).await;
+        let player_model = player.model();
+        player.notify_join(-1, default_location, player_name.clone(), player_model.clone()).await;
         player.set_location(default_location).await;
 
+        let player_id = player.id.upgrade()?.load(Ordering::Relaxed);
         {
-            let mut player_lock = self.players.lock();
-
-            player_lock.insert(id, player.clone());
-            let player_id = player.id.upgrade()?.load(Ordering::Relaxed);
+            let player_lock = self.players.lock();
 
             for (id, other) in player_lock.iter().map(|(i, p)| (*i, p.clone())) {
                 let Some(name) = other.username.upgrade() else { continue; };
@@ -262,20 +764,156 @@ impl World {
                 
                 let other_f = other.clone();
                 let pname_f = player_name.clone();
+                let pmodel_f = player_model.clone();
                 fire_and_forget! {
-                    other_f.notify_join(player_id, default_location, pname_f)
+                    other_f.notify_join(player_id, default_location, pname_f, pmodel_f)
                 }
 
                 if let Some(other_loc) = other.location.upgrade() {
                     let player_f = player.clone();
+                    let other_model = other.model();
                     fire_and_forget! {
-                        player_f.notify_join(id, other_loc.as_ref(), name)
+                        player_f.notify_join(id, other_loc.as_ref(), name, other_model)
                     }
                 }
             }
         }
 
-        Some(id)
+        Some(player_id)
+    }
+
+    /// Sends the whole level to a player who is already in the world, keeping everyone where they are.
+    /// Like [`World::add_player`], this must be called from the player's command loop.
+    /// Returns `None` if the player has disconnected.
+    pub async fn resend_level(&self, player: &WeakPlayer, packet_send: &Sender<Outgoing>) -> Option<()> {
+        let level = Self::snapshot_level(&*self.data.lock().await, player);
+        match level {
+            Ok(level) => Self::send_level(level, packet_send).await?,
+            Err(reason) => {
+                player.notify_disconnect(reason).await;
+                return None;
+            }
+        }
+
+        // Clients forget every entity when they load a level, including themselves
+        let player_id = player.id.upgrade()?.load(Ordering::Relaxed);
+        let others: Vec<_> = self.players.lock().iter().map(|(id, other)| (*id, other.clone())).collect();
+        for (id, other) in others {
+            let Some(name) = other.username.upgrade().and_then(|name| name.get().cloned()) else { continue; };
+            let Some(location) = other.location.upgrade() else { continue; };
+            let id = if id == player_id { -1 } else { id };
+            player.notify_join(id, location.as_ref(), name, other.model()).await;
+        }
+
+        Some(())
+    }
+
+    /// Copies the level and everything else needed to play in the world for a player.
+    /// Errors with the reason to disconnect the player if the world is corrupt.
+    fn snapshot_level(data_lock: &WorldData, player: &WeakPlayer) -> Result<LevelSnapshot, String> {
+        let dimensions = data_lock.level_data.dimensions;
+        let raw_data = data_lock.level_data.raw_data.clone();
+
+        let expected_area = dimensions.x as usize * dimensions.y as usize * dimensions.z as usize;
+        let actual_area = raw_data.len();
+        if actual_area != expected_area {
+            warn!(
+                "World \"{}\" is corrupt (expected {expected_area} bytes for {}x{}x{}, got {actual_area} bytes)",
+                data_lock.name,
+                dimensions.x,
+                dimensions.y,
+                dimensions.z
+            );
+            return Err(format!("World is corrupt (exp. {expected_area} bytes, got {actual_area} bytes)"));
+        }
+
+        let extensions = player.extensions();
+        let support_level = player.block_support_level();
+        let definitions_supported = extensions.contains(Extension::BlockDefinitions);
+        let needs_fallbacks = support_level < blocks::CUSTOM_BLOCK_SUPPORT_LEVEL
+            || !definitions_supported && !data_lock.block_definitions.is_empty();
+        let fallbacks = needs_fallbacks.then(|| Box::new(blocks::fallback_table(
+            support_level,
+            (!definitions_supported).then_some(&data_lock.block_definitions)
+        )));
+
+        let mut packets = Vec::new();
+
+        for (id, definition) in &data_lock.block_definitions {
+            let Some(packet) = definition.packet(*id, extensions) else { break; };
+            packets.push(packet);
+        }
+
+        if extensions.contains(Extension::BlockPermissions) {
+            let username = player.username.upgrade().and_then(|name| name.get().cloned()).unwrap_or_default();
+            packets.extend(data_lock.block_permission_packets(&username, player.rank_level(), player.max_block()));
+        }
+
+        packets.extend(data_lock.environment.packets(dimensions, extensions));
+
+        if extensions.contains(Extension::SetSpawnpoint) {
+            let extended = extensions.contains(Extension::ExtEntityPositions);
+            packets.push(Outgoing::SetSpawnpoint { location: data_lock.spawn_point, extended });
+        }
+
+        if extensions.contains(Extension::HackControl) {
+            packets.push(data_lock.hacks.packet(player.is_operator()));
+        }
+
+        if extensions.contains(Extension::ClickDistance) {
+            packets.push(Outgoing::SetClickDistance { distance: data_lock.reach.unwrap_or(DEFAULT_REACH) });
+        }
+
+        if extensions.contains(Extension::TextHotKey) {
+            packets.extend(data_lock.hotkeys.iter().map(HotKey::packet));
+        }
+
+        if extensions.contains(Extension::InventoryOrder) && !data_lock.palette.is_empty() {
+            packets.extend(blocks::inventory_order(&data_lock.palette));
+        }
+
+        Ok(LevelSnapshot {
+            raw_data,
+            dimensions,
+            fallbacks,
+            fast_map: extensions.contains(Extension::FastMap),
+            packets,
+        })
+    }
+
+    /// Sends a player a copy of the level, up to `LevelFinalize`. The level is compressed on a blocking thread.
+    /// Returns `None` if the player has disconnected.
+    async fn send_level(level: LevelSnapshot, packet_send: &Sender<Outgoing>) -> Option<()> {
+        let LevelSnapshot { raw_data, dimensions, fallbacks, fast_map, packets } = level;
+
+        debug!("{} bytes to compress", raw_data.len());
+
+        #[allow(clippy::cast_possible_truncation)]
+        let Ok(()) = packet_send.send(Outgoing::LevelInit {
+            volume: fast_map.then_some(raw_data.len() as u32)
+        }).await else { return None; };
+
+        let chunk_send = packet_send.clone();
+        tokio::task::spawn_blocking(move || {
+            // FastMap clients get a raw DEFLATE stream without the length prefix
+            let level = WorldEncoder::new(&raw_data, fallbacks, !fast_map);
+            if fast_map {
+                let encoder = DeflateEncoder::new(level, Compression::fast());
+                send_level_chunks(encoder, DeflateEncoder::get_ref, &chunk_send)
+            } else {
+                let encoder = GzEncoder::new(level, Compression::fast());
+                send_level_chunks(encoder, GzEncoder::get_ref, &chunk_send)
+            }
+        }).await.ok()??;
+
+        for packet in packets {
+            let Ok(()) = packet_send.send(packet).await else { return None; };
+        }
+
+        let Ok(()) = packet_send.send(Outgoing::LevelFinalize { size: dimensions }).await
+            else { return None; };
+
+        Some(())
     }
 
     /// Gets a player by their ID.
@@ -337,6 +975,7 @@ impl World {
     ///
     /// This **does not block**, and instead returns a false boolean if the level data is locked.
     pub fn set_block(&self, location: Vector3<u16>, id: u8) -> bool {
+        let (fallback, broken);
         {
             let Ok(mut data_lock) = self.data.try_lock() else {
                 return false;
@@ -347,7 +986,10 @@ impl World {
                 return true;
             };
 
+            broken = *block != 0 && id == 0;
             *block = id;
+
+            fallback = data_lock.block_definitions.get(&id).map_or(id, |definition| definition.fallback);
         }
 
         {
@@ -355,14 +997,70 @@ impl World {
 
             for player in player_lock.values().cloned() {
                 tokio::spawn(async move {
-                    player.set_block(id, location).await;
+                    player.set_block(id, location, fallback).await;
                 }); // No real need to wait for these to send
             }
         }
 
+        if broken {
+            let center = Vector3::from([location.x, location.y, location.z].map(|v| x32::from_bits(i32::from(v) * 32 + 16)));
+            self.spawn_effect(effects::BREAK_EFFECT, center, center);
+        }
+
         true
     }
 
+    /// Sets many blocks in the world at once, notifying all players in the world of the changes in bulk.
+    /// If more than [`RESEND_THRESHOLD`] blocks change, then players are sent the whole level instead.
+    /// Returns the number of blocks that changed.
+    ///
+    /// Unlike [`World::set_block`], this waits for the level data to be unlocked.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub async fn set_blocks(&self, changes: impl IntoIterator<Item = (Vector3<u16>, u8)>) -> usize {
+        let mut updates = Vec::new();
+        {
+            let mut data_lock = self.data.lock().await;
+            let data = &mut *data_lock;
+            for (position, id) in changes {
+                let Some(index) = data.level_data.index(position) else { continue };
+                let block = &mut data.level_data.raw_data[index];
+                if *block == id { continue }
+                *block = id;
+                let fallback = data.block_definitions.get(&id).map_or(id, |definition| definition.fallback);
+                updates.push(BlockChange { position, index: index as i32, id, fallback });
+            }
+        }
+
+        let count = updates.len();
+        if count == 0 {
+            return 0;
+        }
+        let players: Vec<_> = self.players.lock().values().cloned().collect();
+        if count > RESEND_THRESHOLD {
+            for player in players {
+                player.resend_level().await;
+            }
+        } else {
+            let updates: Arc<[BlockChange]> = updates.into();
+            for player in players {
+                player.set_blocks(updates.clone()).await;
+            }
+        }
+        count
+    }
+
+    /// Spawns a particle effect for all players in the world.
+    /// Players whose clients don't support `CustomParticles` see nothing.
+    pub fn spawn_effect(&self, id: u8, position: Vector3<x32>, origin: Vector3<x32>) {
+        let player_lock = self.players.lock();
+
+        for player in player_lock.values().cloned() {
+            tokio::spawn(async move {
+                player.spawn_effect(id, position, origin).await;
+            }); // No real need to wait for these to send
+        }
+    }
+
     /// Move a player in the world, notifying all other players of their movement.
     pub fn move_player(&self, id: i8, location: Location) {
         let player_lock = self.players.lock();
@@ -406,3 +1104,116 @@ impl World {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The highest block ID supported by clients with `BlockDefinitions`.
+    const MAX_BLOCK: u8 = 255;
+
+    /// Builds an empty world that's 32 blocks long on the X axis, with bedrock at X 1 and water at X 2.
+    fn world() -> WorldData {
+        let mut level_data = LevelData::new(vec![0; 32], Vector3 { x: 32, y: 1, z: 1 });
+        *level_data.get_mut(Vector3 { x: 1, y: 0, z: 0 }).unwrap() = 7;
+        *level_data.get_mut(Vector3 { x: 2, y: 0, z: 0 }).unwrap() = 8;
+        WorldData { level_data, ..WorldData::default() }
+    }
+
+    /// Gets the position at the middle of a block on the X axis, offset by some 32nds of a block.
+    fn origin(x: u16, offset: i32) -> Vector3<x32> {
+        Vector3 {
+            x: x32::from_bits(i32::from(x) * 32 + 16 + offset),
+            y: x32::from_bits(16),
+            z: x32::from_bits(16),
+        }
+    }
+
+    /// Checks a block change by a player whose client supports every block.
+    fn check(
+        world: &WorldData,
+        position: Vector3<u16>,
+        id: u8,
+        origin: Vector3<x32>,
+        level: u8,
+    ) -> Result<(), BlockRejection> {
+        world.check_block_change(position, id, origin, level, MAX_BLOCK)
+    }
+
+    /// Gets the position of a block on the X axis.
+    fn block(x: u16) -> Vector3<u16> {
+        Vector3 { x, y: 0, z: 0 }
+    }
+
+    #[test]
+    fn rejects_blocks_outside_the_level() {
+        let world = world();
+        assert_eq!(check(&world, block(32), 1, origin(31, 0), 0), Err(BlockRejection::OutOfBounds));
+        let above = Vector3 { x: 0, y: 1, z: 0 };
+        assert_eq!(check(&world, above, 1, origin(0, 0), 0), Err(BlockRejection::OutOfBounds));
+    }
+
+    #[test]
+    fn rejects_blocks_the_client_doesnt_support() {
+        let world = world();
+        let max_block = blocks::MAX_VANILLA_BLOCK;
+        let id = max_block + 1;
+        let result = world.check_block_change(block(5), id, origin(5, 0), 0, max_block);
+        assert_eq!(result, Err(BlockRejection::InvalidBlock(id)));
+        assert_eq!(world.check_block_change(block(5), max_block, origin(5, 0), 0, max_block), Ok(()));
+    }
+
+    #[test]
+    fn rejects_undefined_custom_blocks() {
+        let mut world = world();
+        let id = blocks::MAX_CUSTOM_BLOCK + 1;
+        assert_eq!(check(&world, block(5), id, origin(5, 0), 0), Err(BlockRejection::InvalidBlock(id)));
+        world.block_definitions.insert(id, BlockDefinition::new("Custom".into(), 1, 1));
+        assert_eq!(check(&world, block(5), id, origin(5, 0), 0), Ok(()));
+    }
+
+    #[test]
+    fn rejects_blocks_out_of_reach() {
+        let world = world();
+        let limit = i32::from(DEFAULT_REACH + REACH_LEEWAY);
+        let target = block(20);
+        assert_eq!(check(&world, target, 1, origin(20, -limit), 0), Ok(()));
+        assert_eq!(check(&world, target, 1, origin(20, -limit - 1), 0), Err(BlockRejection::OutOfReach));
+    }
+
+    #[test]
+    fn operator_blocks_need_the_operator_level() {
+        let world = world();
+        let operator = ranks::OPERATOR_LEVEL;
+        // Placing bedrock into air
+        assert_eq!(check(&world, block(5), 7, origin(5, 0), 0), Err(BlockRejection::CantPlace(7)));
+        assert_eq!(check(&world, block(5), 7, origin(5, 0), operator), Ok(()));
+        // Deleting bedrock
+        assert_eq!(check(&world, block(1), 0, origin(1, 0), 0), Err(BlockRejection::CantDelete(7)));
+        assert_eq!(check(&world, block(1), 0, origin(1, 0), operator), Ok(()));
+    }
+
+    #[test]
+    fn placing_a_block_needs_the_original_to_be_replaceable() {
+        let world = world();
+        let operator = ranks::OPERATOR_LEVEL;
+        // Anyone can build into water, even if they can't delete it
+        assert_eq!(check(&world, block(2), 1, origin(2, 0), 0), Ok(()));
+        assert_eq!(check(&world, block(2), 0, origin(2, 0), 0), Err(BlockRejection::CantDelete(8)));
+        assert_eq!(check(&world, block(2), 1, origin(2, 0), operator), Ok(()));
+        // Real clients never place blocks into solid blocks
+        assert_eq!(check(&world, block(1), 1, origin(1, 0), operator), Err(BlockRejection::CantDelete(7)));
+    }
+
+    #[test]
+    fn players_who_cant_build_cant_use_any_block() {
+        let mut world = world();
+        world.permissions.build = RankRequirement::Nobody;
+        world.permissions.builders.insert("builder".into());
+        let allowed = |username| world.block_permission_packets(username, 0, MAX_BLOCK).into_iter()
+            .filter(|packet| matches!(packet, Outgoing::SetBlockPermission { allow_placement: true, .. }))
+            .count();
+        assert_eq!(allowed("guest"), 0);
+        assert!(allowed("builder") > 0);
+    }
+}